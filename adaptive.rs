@@ -0,0 +1,86 @@
+// Optional adaptive difficulty. A level in `MIN_LEVEL..=MAX_LEVEL` scales how
+// much each wave spends, how fast enemies move and how often they shoot. It
+// starts where the profile's last runs left it and drifts during a run with
+// how the player is doing. Everything it reads comes from the simulation, so
+// a replay that records the starting level plays back exactly.
+
+use serde::Serialize;
+
+pub const MIN_LEVEL: f32 = -0.3;
+pub const MAX_LEVEL: f32 = 0.3;
+/// Seconds between re-evaluations during a run.
+const WINDOW: f32 = 5.0;
+/// Most the level moves in one window, so it never lurches.
+const STEP: f32 = 0.04;
+/// How far in-run performance may pull the level away from where it started.
+const REACH: f32 = 0.15;
+/// A run this long leaves the next one's starting level where it was.
+const PAR_TIME: f32 = 45.0;
+/// Most a single run moves the next one's starting level.
+const CARRY: f32 = 0.06;
+
+pub struct Adaptive {
+    pub start: f32,
+    pub level: f32,
+    timer: f32,
+    near_misses: u32,
+    /// Level integrated over time, for the average.
+    area: f32,
+    time: f32,
+}
+
+/// What the level did over a run, for the summary, history and leaderboard.
+#[derive(Clone, Copy, Serialize)]
+pub struct Adjustment { pub start: f32, pub end: f32, pub average: f32 }
+
+impl Adaptive {
+    pub fn new(start: f32) -> Self {
+        let start = start.clamp(MIN_LEVEL, MAX_LEVEL);
+        Adaptive { start, level: start, timer: WINDOW, near_misses: 0, area: 0.0, time: 0.0 }
+    }
+
+    pub fn near_miss(&mut self) { self.near_misses += 1; }
+
+    /// Every `WINDOW` seconds, steps toward a target set by the near-misses
+    /// since the last window, the current combo and how long the run has
+    /// lasted against `PAR_TIME`.
+    pub fn update(&mut self, dt: f32, combo: f32, survived: f32) {
+        self.area += self.level * dt;
+        self.time += dt;
+        self.timer -= dt;
+        if self.timer > 0.0 { return; }
+        self.timer += WINDOW;
+        let near = (self.near_misses as f32 / 3.0).min(1.0);
+        let combo = combo.clamp(0.0, 1.0);
+        let lasted = (survived / PAR_TIME).min(1.0);
+        self.near_misses = 0;
+        // 0.4 is about what an average player manages
+        let perf = near * 0.4 + combo * 0.4 + lasted * 0.2;
+        let target = (self.start + (perf - 0.4) / 0.6 * REACH).clamp(MIN_LEVEL, MAX_LEVEL);
+        self.level += (target - self.level).clamp(-STEP, STEP);
+    }
+
+    /// Multiplies wave budgets and divides the gaps between spawns and waves.
+    pub fn spawn_scale(&self) -> f32 { 1.0 + self.level }
+    pub fn speed_scale(&self) -> f32 { 1.0 + self.level * 0.5 }
+    /// Divides enemy fire cooldowns.
+    pub fn fire_scale(&self) -> f32 { 1.0 + self.level }
+
+    pub fn adjustment(&self) -> Adjustment {
+        let average = if self.time > 0.0 { self.area / self.time } else { self.level };
+        Adjustment { start: self.start, end: self.level, average }
+    }
+}
+
+/// Where the next run starts: quick deaths ease off, runs past `PAR_TIME`
+/// push harder.
+pub fn next_start(start: f32, survived: f32) -> f32 {
+    let delta = ((survived - PAR_TIME) / PAR_TIME * CARRY).clamp(-CARRY, CARRY);
+    (start + delta).clamp(MIN_LEVEL, MAX_LEVEL)
+}
+
+/// `+12%`, `-5%`, `0%`.
+pub fn percent(level: f32) -> String {
+    let p = (level * 100.0).round() as i32;
+    if p > 0 { format!("+{}%", p) } else { format!("{}%", p) }
+}
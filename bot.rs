@@ -0,0 +1,57 @@
+// A simple scripted player for headless balance runs. It steers away from
+// nearby enemies, bullets, the boss and the walls, drifts toward shards and
+// power-ups, and dashes through anything that gets too close.
+
+use macroquad::math::{vec2, Vec2};
+
+use crate::input::InputState;
+use crate::sim::World;
+
+const THREAT_RANGE: f32 = 200.0;
+const BULLET_RANGE: f32 = 140.0;
+const DASH_RANGE: f32 = 42.0;
+const WALL_RANGE: f32 = 90.0;
+
+#[derive(Default)]
+pub struct Bot;
+
+impl Bot {
+    pub fn think(&mut self, w: &World) -> InputState {
+        let p = w.player.pos;
+        let mut push = Vec2::ZERO;
+        let mut closest = f32::MAX;
+
+        for e in w.enemies.values() {
+            let d = p - e.pos; let dist = d.length();
+            closest = closest.min(dist - e.r);
+            if dist < THREAT_RANGE && dist > 0.0 { push += d / dist * (1.0 - dist / THREAT_RANGE) * 2.0; }
+        }
+        for b in w.bullets.values().filter(|b| b.hostile) {
+            let d = p - b.pos; let dist = d.length();
+            closest = closest.min(dist - b.r);
+            if dist < BULLET_RANGE && dist > 0.0 {
+                // sidestep across the bullet's path rather than running ahead of it
+                let side = vec2(-b.vel.y, b.vel.x).normalize_or_zero();
+                let side = if side.dot(d) < 0.0 { -side } else { side };
+                push += side * (1.0 - dist / BULLET_RANGE) * 3.0;
+            }
+        }
+        if let Some(b) = &w.boss {
+            let d = p - b.pos; let dist = d.length();
+            if dist > 0.0 { push += d / dist * (1.0 - (dist / (THREAT_RANGE + b.r)).min(1.0)) * 4.0; }
+        }
+
+        if p.x < WALL_RANGE { push.x += 1.0 - p.x / WALL_RANGE; }
+        if p.x > w.arena.x - WALL_RANGE { push.x -= 1.0 - (w.arena.x - p.x) / WALL_RANGE; }
+        if p.y < WALL_RANGE { push.y += 1.0 - p.y / WALL_RANGE; }
+        if p.y > w.arena.y - WALL_RANGE { push.y -= 1.0 - (w.arena.y - p.y) / WALL_RANGE; }
+
+        let target = w.shards.values().map(|s| s.pos)
+            .chain(w.powerups.values().map(|pu| pu.pos))
+            .min_by(|a, b| p.distance_squared(*a).total_cmp(&p.distance_squared(*b)));
+        if let Some(t) = target { push += (t - p).normalize_or_zero() * 0.6; }
+
+        let dash = closest < DASH_RANGE && w.player.dashes_left > 0 && !w.player.is_dashing();
+        InputState { mv: push.normalize_or_zero(), dash, ..Default::default() }
+    }
+}
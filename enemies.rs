@@ -0,0 +1,244 @@
+// Enemy archetypes, read from `data/enemies.json`. The table is built into the
+// binary; `--enemies <file>` swaps in another one so enemies can be added and
+// rebalanced without a rebuild. An enemy only stores its index in the table.
+
+use macroquad::math::Vec2;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::save::fnv;
+use crate::sim::{heading, MAX_BULLET_RADIUS};
+
+const BUILT_IN: &str = include_str!("data/enemies.json");
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Movement {
+    /// Drifts on a noise field, pulled gently toward the middle.
+    Wander,
+    /// Heads straight for the player.
+    Chase,
+    /// Circles the player at `radius`, `spin` radians per second.
+    Orbit { radius: f32, spin: f32 },
+    /// Closes in until within `range`, stops for `windup` seconds with its
+    /// heading locked, then lunges along it; `rest` seconds before the next.
+    Charge { range: f32, windup: f32, lunge_speed: f32, lunge_time: f32, rest: f32 },
+    /// Holds about `distance` from the player, sidling around it.
+    Keep { distance: f32 },
+    /// Stays put, its radius swelling by `grow` times every `period` seconds.
+    Anchor { period: f32, grow: f32 },
+    /// Boids: keeps apart from, lines up and bunches with its own kind within
+    /// `range` while homing on the player. `turn` is how fast it steers.
+    Flock { range: f32, turn: f32 },
+}
+
+impl Movement {
+    /// Zero periods and distances end up dividing by zero in `Enemy::update`,
+    /// and a NaN radius or velocity makes an enemy hit from anywhere.
+    fn check(&self) -> Result<(), String> {
+        let ok = match *self {
+            Movement::Wander | Movement::Chase => true,
+            Movement::Orbit { radius, spin } => radius > 0.0 && spin >= 0.0,
+            Movement::Charge { range, windup, lunge_speed, lunge_time, rest } =>
+                range > 0.0 && lunge_time > 0.0 && windup >= 0.0 && lunge_speed >= 0.0 && rest >= 0.0,
+            Movement::Keep { distance } => distance > 0.0,
+            Movement::Anchor { period, grow } => period > 0.0 && grow >= 0.0,
+            Movement::Flock { range, turn } => range > 0.0 && turn >= 0.0,
+        };
+        if ok { Ok(()) } else {
+            Err("movement needs positive radius, range, lunge_time, distance and period, and no negative spin, windup, lunge_speed, rest, grow or turn".into())
+        }
+    }
+}
+
+/// How an archetype is drawn.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Look { #[default] Orb, Charger, Splitter, Mine, Sniper, Dart }
+
+/// Where new enemies appear.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpawnAt {
+    /// Just outside a random edge.
+    #[default]
+    Edge,
+    /// Somewhere in the arena, clear of the player.
+    Inside,
+}
+
+/// Replaces an enemy killed by a dash with `count` of another archetype.
+#[derive(Clone, Deserialize)]
+pub struct Split {
+    pub into: String,
+    pub count: u32,
+    /// `into` resolved to an index when the table loads.
+    #[serde(skip)]
+    pub kind: usize,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Pattern {
+    Aimed,
+    /// `count` bullets fanned across `arc` radians, centred on the player.
+    Spread { count: u32, arc: f32 },
+    /// `count` bullets evenly around the enemy.
+    Ring { count: u32 },
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct Fire {
+    pub pattern: Pattern,
+    pub speed: f32,
+    pub radius: f32,
+    pub life: f32,
+    /// Seconds between volleys, rolled between the two values.
+    pub cooldown: [f32; 2],
+}
+
+impl Fire {
+    /// Bullet velocities for one volley from `from` at `target`.
+    pub fn volley(&self, from: Vec2, target: Vec2) -> Vec<Vec2> {
+        let aim = (target - from).normalize_or_zero();
+        let base = libm::atan2f(aim.y, aim.x);
+        let dirs: Vec<f32> = match self.pattern {
+            Pattern::Aimed => vec![base],
+            Pattern::Spread { count, arc } => {
+                let n = count.max(1);
+                if n == 1 { vec![base] } else { (0..n).map(|i| base - arc * 0.5 + arc * i as f32 / (n - 1) as f32).collect() }
+            }
+            Pattern::Ring { count } => (0..count).map(|i| base + std::f32::consts::TAU * i as f32 / count as f32).collect(),
+        };
+        dirs.into_iter().map(|a| heading(a) * self.speed).collect()
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Archetype {
+    pub name: String,
+    /// What one spawn (a whole `group`) takes out of a wave's budget.
+    #[serde(default = "one_cost")]
+    pub cost: f32,
+    pub radius: f32,
+    pub speed: f32,
+    /// 0..1 around the colour wheel.
+    pub hue: f32,
+    pub movement: Movement,
+    #[serde(default)]
+    pub fire: Option<Fire>,
+    #[serde(default)]
+    pub split: Option<Split>,
+    #[serde(default)]
+    pub look: Look,
+    /// Relative chance of being picked for a wave among the archetypes it
+    /// allows. Zero never spawns on its own, only through `split`.
+    pub weight: f32,
+    /// Seconds into a run before this archetype can spawn.
+    pub first_at: f32,
+    #[serde(default)]
+    pub spawn_at: SpawnAt,
+    /// How many arrive together.
+    #[serde(default = "one")]
+    pub group: u32,
+    /// No more spawn while this many are alive.
+    #[serde(default)]
+    pub max_alive: Option<u32>,
+}
+
+fn one() -> u32 { 1 }
+fn one_cost() -> f32 { 1.0 }
+
+impl Archetype {
+    /// The most this archetype's collision radius ever reaches.
+    pub fn max_radius(&self) -> f32 {
+        match self.movement { Movement::Anchor { grow, .. } => self.radius * (1.0 + grow.max(0.0)), _ => self.radius }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Registry {
+    pub archetypes: Vec<Archetype>,
+    /// Identifies the table, so a replay can tell it was recorded with another.
+    #[serde(skip)]
+    pub fingerprint: u64,
+    #[serde(skip)]
+    pub max_radius: f32,
+}
+
+impl Registry {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut r: Registry = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if r.archetypes.is_empty() { return Err("no archetypes".into()); }
+        let names: Vec<String> = r.archetypes.iter().map(|a| a.name.clone()).collect();
+        for a in &mut r.archetypes {
+            if let Some(s) = &mut a.split {
+                s.kind = names.iter().position(|n| *n == s.into).ok_or_else(|| format!("{}: splits into unknown archetype {}", a.name, s.into))?;
+                if names[s.kind] == a.name { return Err(format!("{}: cannot split into itself", a.name)); }
+            }
+            if a.radius <= 0.0 || a.cost <= 0.0 || a.speed < 0.0 || a.weight < 0.0 || a.first_at < 0.0 {
+                return Err(format!("{}: radius and cost must be positive and speed, weight and first_at not negative", a.name));
+            }
+            a.movement.check().map_err(|e| format!("{}: {}", a.name, e))?;
+            if let Some(f) = &a.fire {
+                if f.radius > MAX_BULLET_RADIUS { return Err(format!("{}: bullet radius is capped at {}", a.name, MAX_BULLET_RADIUS)); }
+                if f.cooldown[0] <= 0.0 || f.cooldown[1] < f.cooldown[0] { return Err(format!("{}: cooldown must be [min, max] with 0 < min <= max", a.name)); }
+            }
+        }
+        r.fingerprint = fnv(text.as_bytes());
+        r.max_radius = r.archetypes.iter().map(Archetype::max_radius).fold(0.0, f32::max);
+        Ok(r)
+    }
+
+    pub fn get(&self, kind: usize) -> &Archetype { &self.archetypes[kind] }
+
+    pub fn find(&self, name: &str) -> Option<usize> { self.archetypes.iter().position(|a| a.name == name) }
+}
+
+/// The table in use; the built-in one unless `install` ran first.
+pub fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry::parse(BUILT_IN).unwrap_or_else(|e| panic!("built-in data/enemies.json: {}", e)))
+}
+
+/// Replaces the built-in table with the one in `path`. Only works before the
+/// first `World` is made.
+pub fn install(path: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let r = Registry::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    REGISTRY.set(r).map_err(|_| "the enemy table is already in use".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(movement: &str) -> String {
+        format!(r#"{{ "archetypes": [{{ "name": "x", "radius": 10, "speed": 100, "hue": 0.5, "weight": 1, "first_at": 0, "movement": {} }}] }}"#, movement)
+    }
+
+    #[test]
+    fn built_in_table_loads() { assert!(Registry::parse(BUILT_IN).is_ok()); }
+
+    #[test]
+    fn movement_parameters_are_checked() {
+        for good in [r#"{ "type": "chase" }"#, r#"{ "type": "anchor", "period": 1.5, "grow": 0 }"#, r#"{ "type": "keep", "distance": 300 }"#] {
+            assert!(Registry::parse(&table(good)).is_ok(), "{}", good);
+        }
+        for bad in [
+            r#"{ "type": "anchor", "period": 0, "grow": 0.5 }"#,
+            r#"{ "type": "anchor", "period": 1, "grow": -0.5 }"#,
+            r#"{ "type": "keep", "distance": 0 }"#,
+            r#"{ "type": "orbit", "radius": 100, "spin": -1 }"#,
+            r#"{ "type": "orbit", "radius": 0, "spin": 1 }"#,
+            r#"{ "type": "charge", "range": 200, "windup": 0.5, "lunge_speed": 500, "lunge_time": 0, "rest": 1 }"#,
+            r#"{ "type": "flock", "range": 0, "turn": 2 }"#,
+        ] {
+            let err = Registry::parse(&table(bad)).err().unwrap_or_else(|| panic!("accepted {}", bad));
+            assert!(err.starts_with("x: movement"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Uniform-grid broadphase. Entities are bucketed by position once per tick and
+// proximity queries only look at the buckets a circle overlaps. Anything
+// outside the arena is clamped into the border cells so it is never missed.
+
+use macroquad::math::Vec2;
+use crate::pool::Handle;
+
+pub struct Grid { cell: f32, cols: usize, rows: usize, cells: Vec<Vec<Handle>> }
+
+impl Grid {
+    pub fn new(cell: f32) -> Self { Self { cell, cols: 0, rows: 0, cells: vec![] } }
+
+    /// Empties every bucket, resizing the grid to cover `arena`. Buckets keep
+    /// their capacity so steady-state rebuilds do not allocate.
+    pub fn clear(&mut self, arena: Vec2){
+        self.cols = ((arena.x / self.cell).ceil() as usize).max(1);
+        self.rows = ((arena.y / self.cell).ceil() as usize).max(1);
+        let n = self.cols * self.rows;
+        if self.cells.len() < n { self.cells.resize_with(n, Vec::new); }
+        for c in &mut self.cells { c.clear(); }
+    }
+
+    fn cell_of(&self, p: Vec2) -> (usize, usize) {
+        let cx = (p.x / self.cell).floor().clamp(0.0, (self.cols - 1) as f32) as usize;
+        let cy = (p.y / self.cell).floor().clamp(0.0, (self.rows - 1) as f32) as usize;
+        (cx, cy)
+    }
+
+    pub fn insert(&mut self, id: Handle, pos: Vec2){
+        let (cx, cy) = self.cell_of(pos);
+        self.cells[cy * self.cols + cx].push(id);
+    }
+
+    /// Collects, in slot order, every handle whose bucket overlaps the circle.
+    /// Callers still do the exact distance test; `radius` must include the
+    /// largest radius of the entities stored.
+    pub fn query(&self, pos: Vec2, radius: f32, out: &mut Vec<Handle>){
+        out.clear();
+        let (x0, y0) = self.cell_of(pos - Vec2::splat(radius));
+        let (x1, y1) = self.cell_of(pos + Vec2::splat(radius));
+        for cy in y0..=y1 {
+            for cx in x0..=x1 { out.extend_from_slice(&self.cells[cy * self.cols + cx]); }
+        }
+        out.sort_unstable();
+    }
+}
@@ -0,0 +1,82 @@
+// Append-only run history, one JSON object per line, for digging through
+// progression with external tools. Lives next to the profile's save.
+
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::adaptive::{Adaptive, Adjustment};
+use crate::sim::{Breakdown, DeathCause, Upgrades, World};
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Serialize)]
+pub struct RunRecord {
+    /// Unix seconds and the same instant as UTC ISO 8601.
+    pub timestamp: u64,
+    pub date: String,
+    pub seed: u64,
+    pub duration: f32,
+    pub score: i32,
+    /// Highest multiplier reached, as shown in the HUD.
+    pub combo_peak: f32,
+    pub credits: i32,
+    pub score_by: Breakdown,
+    pub credits_by: Breakdown,
+    pub upgrades: Upgrades,
+    pub bosses_cleared: u32,
+    pub cause: Option<DeathCause>,
+    pub death_pos: Option<[f32; 2]>,
+    /// Absent when the run played without adaptive difficulty.
+    pub adaptive: Option<Adjustment>,
+}
+
+impl RunRecord {
+    pub fn new(w: &World) -> Self {
+        let timestamp = now();
+        RunRecord {
+            timestamp,
+            date: utc_string(timestamp),
+            seed: w.seed,
+            duration: w.time,
+            score: w.score,
+            combo_peak: 1.0 + w.combo_peak,
+            credits: w.credits,
+            score_by: w.score_by.clone(),
+            credits_by: w.credits_by.clone(),
+            upgrades: w.upgrades.clone(),
+            bosses_cleared: w.bosses_cleared,
+            cause: w.death.map(|(c, _)| c),
+            death_pos: w.death.map(|(_, p)| [p.x, p.y]),
+            adaptive: w.adaptive.as_ref().map(Adaptive::adjustment),
+        }
+    }
+}
+
+pub fn append(path: &Path, rec: &RunRecord) -> io::Result<()> {
+    if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+    let mut line = serde_json::to_string(rec)?;
+    line.push('\n');
+    // one write per record so a crash cannot leave half a line behind
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())
+}
+
+pub fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) }
+
+/// `2024-05-01T13:45:00Z`, from days-since-epoch with the usual civil
+/// calendar conversion.
+pub fn utc_string(secs: u64) -> String {
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + (m <= 2) as i64;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", y, m, d, rem / 3600, rem / 60 % 60, rem % 60)
+}
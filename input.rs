@@ -0,0 +1,68 @@
+// Player intent for one frame, decoupled from where it came from. The live game
+// reads the keyboard; tests, bots and replays hand the game a script instead.
+
+use macroquad::prelude::*;
+
+use crate::view::screen_to_arena;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct InputState {
+    pub mv: Vec2,
+    pub dash: bool,
+    pub pause: bool,
+    pub shop: bool,
+    pub shop_up: bool,
+    pub shop_down: bool,
+    pub shop_buy: bool,
+    pub shop_pick: Option<usize>,
+    pub aim: Vec2,
+}
+
+impl InputState {
+    /// The same state with one-shot presses cleared, for the extra ticks that
+    /// run inside a single frame.
+    pub fn held(&self) -> Self { Self { mv: self.mv, aim: self.aim, ..Default::default() } }
+}
+
+pub trait InputSource {
+    fn poll(&mut self) -> InputState;
+}
+
+pub struct Keyboard;
+
+impl InputSource for Keyboard {
+    fn poll(&mut self) -> InputState {
+        let left = is_key_down(KeyCode::A) || is_key_down(KeyCode::Left);
+        let right= is_key_down(KeyCode::D) || is_key_down(KeyCode::Right);
+        let up   = is_key_down(KeyCode::W) || is_key_down(KeyCode::Up);
+        let down = is_key_down(KeyCode::S) || is_key_down(KeyCode::Down);
+        let digits = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5];
+        let (mx, my) = mouse_position();
+        InputState {
+            mv: vec2((right as i32 - left as i32) as f32, (down as i32 - up as i32) as f32),
+            dash: is_key_pressed(KeyCode::Space),
+            pause: is_key_pressed(KeyCode::P),
+            shop: is_key_pressed(KeyCode::U),
+            shop_up: is_key_pressed(KeyCode::Up),
+            shop_down: is_key_pressed(KeyCode::Down),
+            shop_buy: is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter),
+            shop_pick: digits.iter().position(|k| is_key_pressed(*k)),
+            aim: screen_to_arena(vec2(mx, my)),
+        }
+    }
+}
+
+/// Plays back a fixed list of states, then stands still.
+pub struct Scripted { states: Vec<InputState>, at: usize }
+
+impl Scripted {
+    pub fn new(states: Vec<InputState>) -> Self { Self { states, at: 0 } }
+}
+
+impl InputSource for Scripted {
+    fn poll(&mut self) -> InputState {
+        let s = self.states.get(self.at).copied().unwrap_or_default();
+        self.at += 1;
+        s
+    }
+}
@@ -0,0 +1,94 @@
+// The machine-wide high score table, top runs per mode, shared by every
+// profile. Runs from profiles marked as modified never get in, and the file
+// is signed like a save so a board edited by hand is thrown out.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::save::{mac, verify, write_atomic};
+
+pub const LEADERBOARD_FILE: &str = "leaderboard.json";
+pub const SIZE: usize = 10;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub profile: String,
+    pub score: i32,
+    pub date: String,
+    pub duration: f32,
+    pub seed: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Leaderboard {
+    #[serde(default)]
+    modes: BTreeMap<String, Vec<Entry>>,
+}
+
+impl Leaderboard {
+    /// A missing file is an empty board; an unsigned or edited one is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut v: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        if !verify(&mut v) { return Err("edited outside the game or not signed".into()); }
+        serde_json::from_value(v).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut v = serde_json::to_value(self)?;
+        let sig = mac(&v);
+        if let Value::Object(m) = &mut v { m.insert("mac".into(), sig.into()); }
+        write_atomic(path, serde_json::to_string_pretty(&v)?.as_bytes())
+    }
+
+    pub fn top(&self, mode: &str) -> &[Entry] { self.modes.get(mode).map_or(&[], Vec::as_slice) }
+
+    /// Where a score would land, if it makes the board at all. Ties go below
+    /// the runs already there.
+    pub fn rank(&self, mode: &str, score: i32) -> Option<usize> {
+        let top = self.top(mode);
+        let at = top.iter().position(|e| score > e.score).unwrap_or(top.len());
+        (at < SIZE && score > 0).then_some(at)
+    }
+
+    pub fn insert(&mut self, mode: &str, entry: Entry) -> Option<usize> {
+        let at = self.rank(mode, entry.score)?;
+        let top = self.modes.entry(mode.to_string()).or_default();
+        top.insert(at, entry);
+        top.truncate(SIZE);
+        Some(at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edited_board_does_not_load() {
+        let dir = std::env::temp_dir().join(format!("neon_rush_test_{}_board", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LEADERBOARD_FILE);
+        let mut board = Leaderboard::default();
+        let entry = Entry { name: "Ann".into(), profile: "Player".into(), score: 120, date: "2026-01-01".into(), duration: 31.7, seed: 9 };
+        assert_eq!(board.insert("standard", entry), Some(0));
+        board.save(&path).unwrap();
+        assert_eq!(Leaderboard::load(&path).unwrap().top("standard")[0].score, 120);
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, text.replace("120", "9999")).unwrap();
+        assert!(Leaderboard::load(&path).is_err());
+        fs::write(&path, r#"{"modes": {}}"#).unwrap();
+        assert!(Leaderboard::load(&path).is_err());
+    }
+}
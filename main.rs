@@ -1,87 +1,36 @@
 use macroquad::prelude::*;
 use ::rand::Rng;
 use ::rand::thread_rng;
-use std::fs;
-
-const PLAYER_SPEED: f32 = 360.0;
-const PLAYER_RADIUS: f32 = 15.0;
-const DASH_SPEED: f32 = 920.0;
-const DASH_TIME: f32 = 0.16;
-const DASH_COOLDOWN: f32 = 0.9;
-
-const ENEMY_BASE_SPEED: f32 = 120.0;
-const ENEMY_SPAWN_START: f32 = 1.15;
-const ENEMY_SPAWN_MIN: f32 = 0.26;
-
-const SHARD_SPAWN_RATE: f32 = 1.1;
-const SHARD_RADIUS: f32 = 9.0;
-
-const NEAR_MISS_DIST: f32 = 36.0;
-const NEAR_MISS_BONUS: i32 = 3;
-
-const COMBO_TIME: f32 = 2.2;
-const COMBO_INC: f32 = 0.1;
-const TRAIL_MAX: usize = 42;
-
-
-const SAVE_FILE: &str = "neon_rush.sav";
-
-#[derive(Clone, Default)]
-struct Upgrades {
-    speed: u32,
-    dash_cd: u32,
-    dash_time: u32,
-    shard_value: u32,
-    magnet: u32,
-}
-
-impl Upgrades {
-    fn player_speed(&self) -> f32 { PLAYER_SPEED * (1.0 + 0.06 * self.speed as f32) }
-    fn dash_cd(&self) -> f32 { DASH_COOLDOWN * (0.88f32).powf(self.dash_cd as f32) }
-    fn dash_time(&self) -> f32 { DASH_TIME * (1.0 + 0.08 * self.dash_time as f32) }
-    fn shard_currency_bonus(&self) -> i32 { (self.shard_value as i32) * 2 }
-    fn magnet_speed(&self) -> f32 { 120.0 + 50.0 * self.magnet as f32 }
-
-    fn cost_speed(&self) -> i32 { 60 + (self.speed as i32) * 45 }
-    fn cost_dash_cd(&self) -> i32 { 80 + (self.dash_cd as i32) * 50 }
-    fn cost_dash_time(&self) -> i32 { 80 + (self.dash_time as i32) * 50 }
-    fn cost_shard_value(&self) -> i32 { 40 + (self.shard_value as i32) * 30 }
-    fn cost_magnet(&self) -> i32 { 50 + (self.magnet as i32) * 40 }
-}
-
-fn save_to_disk(currency: i32, upgrades: &Upgrades, best: i32) {
-    let s = format!(
-        "currency={}\nspeed={}\ndash_cd={}\ndash_time={}\nshard_value={}\nmagnet={}\nbest={}\n",
-        currency, upgrades.speed, upgrades.dash_cd, upgrades.dash_time, upgrades.shard_value, upgrades.magnet, best
-    );
-    let _ = fs::write(SAVE_FILE, s);
-}
-
-fn load_from_disk() -> Option<(i32, Upgrades, i32)> {
-    if let Ok(s) = fs::read_to_string(SAVE_FILE) {
-        let mut cur = 0;
-        let mut up = Upgrades::default();
-        let mut best = 0;
-        for line in s.lines() {
-            let mut it = line.splitn(2, '=');
-            if let (Some(k), Some(v)) = (it.next(), it.next()) {
-                match k.trim() {
-                    "currency" => cur = v.trim().parse().unwrap_or(0),
-                    "speed" => up.speed = v.trim().parse().unwrap_or(0),
-                    "dash_cd" => up.dash_cd = v.trim().parse().unwrap_or(0),
-                    "dash_time" => up.dash_time = v.trim().parse().unwrap_or(0),
-                    "shard_value" => up.shard_value = v.trim().parse().unwrap_or(0),
-                    "magnet" => up.magnet = v.trim().parse().unwrap_or(0),
-                    "best" => best = v.trim().parse().unwrap_or(0),
-                    _ => {}
-                }
-            }
-        }
-        return Some((cur, up, best));
-    }
-    None
-}
 
+mod adaptive;
+mod bot;
+mod enemies;
+mod grid;
+mod history;
+mod input;
+mod leaderboard;
+mod paths;
+mod pool;
+mod profile;
+mod replay;
+mod save;
+mod sim;
+mod simulate;
+mod stats;
+mod view;
+mod waves;
+use adaptive::Adaptive;
+use history::{RunRecord, HISTORY_FILE};
+use input::{InputSource, InputState, Keyboard};
+use leaderboard::{Entry, Leaderboard, LEADERBOARD_FILE};
+use replay::{Change, Ghost, Playback, Replay, REPLAY_DIR};
+use save::{SaveData, SAVE_FILE};
+use enemies::Look;
+use sim::{Event, PowerUpKind, Stage, Upgrades, World, ARENA};
+use stats::Stats;
+use std::path::{Path, PathBuf};
+
+const NOTICE_TIME: f32 = 6.0;
 
 fn window_conf() -> Conf {
     Conf {
@@ -95,9 +44,30 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut game = Game::new();
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("simulate") {
+        if let Err(e) = simulate::run(&args[2..]) { eprintln!("simulate: {}", e); std::process::exit(2); }
+        return;
+    }
+    if let Some(path) = arg_value("--enemies") {
+        if let Err(e) = enemies::install(Path::new(&path)) { eprintln!("using the built-in enemies: {}", e); }
+    }
+    // after the enemies, since wave themes name archetypes
+    if let Some(path) = arg_value("--waves") {
+        if let Err(e) = waves::install(Path::new(&path)) { eprintln!("using the built-in waves: {}", e); }
+    }
+    macroquad::Window::from_config(window_conf(), run());
+}
+
+async fn run() {
+    let mut game = Game::new(arg_seed(), paths::data_dir(arg_value("--data-dir")), arg_value("--profile"));
+    if let Some(path) = arg_value("--replay") {
+        match Replay::load(Path::new(&path)) {
+            Ok(r) => game.start_playback(&r),
+            Err(e) => eprintln!("could not load replay {}", e),
+        }
+    }
     loop {
         let dt = get_frame_time();
         game.handle_input();
@@ -107,8 +77,31 @@ async fn main() {
     }
 }
 
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|a| a != name);
+    args.next()?;
+    args.next()
+}
+
+fn arg_seed() -> Option<u64> {
+    match arg_value("--seed").map(|v| v.parse::<u64>()) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => { eprintln!("--seed expects an unsigned integer"); None }
+        None => None,
+    }
+}
+
+fn new_seed() -> u64 { thread_rng().gen() }
+
+fn load_ghost(dir: &Path, seed: u64) -> Option<Ghost> {
+    let path = replay::best_path(dir, seed);
+    if !path.exists() { return None; }
+    match Replay::load(&path) {
+        Ok(r) => Some(Ghost::new(&r)),
+        Err(e) => { eprintln!("could not load ghost {}", e); None }
+    }
+}
 
-fn clamp(v: f32, lo: f32, hi: f32) -> f32 { v.max(lo).min(hi) }
 
 fn hsla(h: f32, s: f32, l: f32, a: u8) -> Color {
 
@@ -141,517 +134,594 @@ impl Particle {
 struct TextFx { pos: Vec2, vel: Vec2, life: f32, text: String, color: Color }
 impl TextFx { fn update(&mut self, dt: f32){ self.pos += self.vel*dt; self.life -= dt; } }
 
-struct Player {
-    pos: Vec2,
-    vel: Vec2,
-    r: f32,
-    dash_cd: f32,
-    dash_t: f32,
-    invuln: f32,
-    dashes_left: i32,
-    dashes_max: i32,
-    trail: Vec<(Vec2, f32)>,
-}
-impl Player {
-    fn new() -> Self { Self { pos: vec2(screen_width()/2.0, screen_height()/2.0), vel: Vec2::ZERO, r: PLAYER_RADIUS, dash_cd: 0.0, dash_t: 0.0, invuln: 0.0, dashes_left: 1, dashes_max: 1, trail: Vec::new() } }
-    fn is_dashing(&self) -> bool { self.dash_t > 0.0 }
-    fn update(&mut self, dt: f32, move_speed: f32) {
-        let left = is_key_down(KeyCode::A) || is_key_down(KeyCode::Left);
-        let right= is_key_down(KeyCode::D) || is_key_down(KeyCode::Right);
-        let up   = is_key_down(KeyCode::W) || is_key_down(KeyCode::Up);
-        let down = is_key_down(KeyCode::S) || is_key_down(KeyCode::Down);
-        let mut mv = vec2((right as i32 - left as i32) as f32, (down as i32 - up as i32) as f32);
-        if mv.length_squared() > 0.0 { mv = mv.normalize(); }
-
-        if self.is_dashing(){
-            self.dash_t -= dt;
-        } else {
-            self.vel = mv * move_speed;
-            if self.dash_cd>0.0 {
-                self.dash_cd -= dt;
-                if self.dash_cd <= 0.0 { self.dashes_left = self.dashes_max; self.dash_cd = 0.0; }
-            }
-        }
-        self.pos += self.vel * dt;
-        self.pos.x = clamp(self.pos.x, self.r, screen_width()-self.r);
-        self.pos.y = clamp(self.pos.y, self.r, screen_height()-self.r);
-        if self.invuln>0.0 { self.invuln -= dt; }
-
-        self.trail.push((self.pos, 0.35));
-        if self.trail.len()>TRAIL_MAX { self.trail.remove(0); }
-        for p in &mut self.trail { p.1 -= dt; }
-        self.trail.retain(|p| p.1 > 0.0);
-    }
-    fn try_dash(&mut self, dash_time: f32, dash_cd_total: f32) -> bool {
-        if self.dashes_left > 0 && !self.is_dashing(){
-            let dir = if self.vel.length_squared()==0.0 { vec2(1.0,0.0) } else { self.vel.normalize() };
-            self.vel = dir * DASH_SPEED;
-            self.dash_t = dash_time;
-            self.invuln = dash_time;
-            self.dashes_left -= 1;
-            if self.dashes_left == 0 { self.dash_cd = dash_cd_total; }
-            return true;
-        }
-        false
-    }
-    fn draw(&self, t: f32, shake: Vec2) {
+impl sim::Player {
+    fn draw(&self, t: f32, shake: Vec2, alpha: f32) {
+        let p = self.prev.lerp(self.pos, alpha) + shake;
 
         for (i, a) in [30u8, 60, 100].iter().enumerate(){
             let rad = self.r + (i as f32)*6.0;
             let c = hsla(0.33 + 0.05*(t*2.0).sin(), 0.9, 0.55, *a);
-            draw_circle(self.pos.x+shake.x, self.pos.y+shake.y, rad, c);
+            draw_circle(p.x, p.y, rad, c);
         }
         let core = hsla(0.55 + 0.25*(t*1.2).sin(), 0.8, 0.55, 255);
-        for (p, life) in &self.trail {
+        for (tp, life) in &self.trail {
             let sz = self.r*0.6 + life*10.0;
             let c = hsla(0.52, 0.9, 0.7, (150.0*life) as u8);
-            draw_circle(p.x+shake.x, p.y+shake.y, sz, c);
+            draw_circle(tp.x+shake.x, tp.y+shake.y, sz, c);
         }
-        draw_circle(self.pos.x+shake.x, self.pos.y+shake.y, self.r, core);
+        draw_circle(p.x, p.y, self.r, core);
         if self.is_dashing(){
-            draw_circle_lines(self.pos.x+shake.x, self.pos.y+shake.y, self.r*1.9, 2.0, hsla(0.52,0.9,0.7,220));
+            draw_circle_lines(p.x, p.y, self.r*1.9, 2.0, hsla(0.52,0.9,0.7,220));
+        }
+    }
+    fn draw_ghost(&self, shake: Vec2, alpha: f32) {
+        let p = self.prev.lerp(self.pos, alpha) + shake;
+        for (tp, life) in &self.trail {
+            draw_circle(tp.x+shake.x, tp.y+shake.y, self.r*0.5 + life*6.0, Color::from_rgba(220,230,255,(60.0*life) as u8));
         }
+        draw_circle(p.x, p.y, self.r, Color::from_rgba(220,230,255,60));
+        draw_circle_lines(p.x, p.y, self.r, 1.5, Color::from_rgba(220,230,255,120));
     }
 }
 
-struct Enemy { pos: Vec2, kind: i32, r: f32, angle: f32, speed: f32, cool: f32 }
-impl Enemy {
-    fn new(pos: Vec2, kind: i32) -> Self { Self{ pos, kind, r: if kind!=2 {12.0} else {10.0}, angle: rand_angle(), speed: ENEMY_BASE_SPEED*(1.0+0.15*(kind as f32)), cool: 0.8 } }
-    fn update(&mut self, dt: f32, player: &Player, t: f32){
-        let v = match self.kind {
-            0 => {
-                let to_c = (vec2(screen_width()/2.0, screen_height()/2.0) - self.pos) * 0.2;
-                let n = vec2((t*1.7 + self.pos.x*0.01).cos(), (t*1.3 + self.pos.y*0.01).sin());
-                let sum = to_c + n*120.0; if sum.length_squared()>0.0 { sum.normalize() } else { Vec2::ZERO }
+impl sim::Enemy {
+    fn draw(&self, t: f32, shake: Vec2, alpha: f32, player: Vec2){
+        let p = self.prev.lerp(self.pos, alpha) + shake;
+        let a = enemies::registry().get(self.kind);
+        let core = hsla(a.hue + 0.05*(t*2.0).sin(), 0.85, 0.55, 255);
+        let ring = hsla(a.hue, 0.9, 0.75, 255);
+        let facing = |v: Vec2| { let d = v.normalize_or_zero(); if d == Vec2::ZERO { vec2(1.0, 0.0) } else { d } };
+        match a.look {
+            Look::Orb => {
+                draw_circle_lines(p.x, p.y, self.r+3.0, 2.0, ring);
+                draw_circle(p.x, p.y, self.r, core);
             }
-            1 => {
-                let mut v = player.pos - self.pos; if v.length_squared()>0.0 { v = v.normalize(); } v
+            Look::Charger => {
+                let d = facing(if self.stage == Stage::Approach { self.vel } else { self.dir });
+                let side = vec2(-d.y, d.x);
+                if self.stage == Stage::Windup {
+                    // telegraph the lunge: a flashing lane along the locked heading
+                    let flash = if (t*16.0).sin() > 0.0 { 200 } else { 90 };
+                    let end = p + d * 220.0;
+                    draw_line(p.x, p.y, end.x, end.y, self.r*2.0, hsla(a.hue, 0.9, 0.6, flash / 3));
+                    draw_circle_lines(p.x, p.y, self.r+6.0, 2.0, hsla(a.hue, 0.9, 0.8, flash));
+                }
+                let (tip, l, r) = (p + d*self.r*1.4, p - d*self.r + side*self.r, p - d*self.r - side*self.r);
+                draw_triangle(tip, l, r, core);
+                draw_triangle_lines(tip, l, r, 2.0, ring);
             }
-            _ => {
-                let offset = vec2(self.angle.cos(), self.angle.sin()) * 120.0;
-                let mut v = (player.pos + offset) - self.pos; if v.length_squared()>0.0 { v = v.normalize(); }
-                self.angle += 2.5*dt; v
+            Look::Splitter => {
+                draw_circle_lines(p.x, p.y, self.r+3.0, 2.0, ring);
+                draw_circle(p.x, p.y, self.r, hsla(a.hue, 0.7, 0.3, 255));
+                let n = a.split.as_ref().map_or(2, |s| s.count.max(1));
+                for i in 0..n {
+                    let ang = t*1.5 + i as f32 * std::f32::consts::TAU / n as f32;
+                    let c = p + vec2(ang.cos(), ang.sin()) * self.r * 0.45;
+                    draw_circle(c.x, c.y, self.r*0.38, core);
+                }
             }
-        };
-        self.pos += v * self.speed * dt;
+            Look::Mine => {
+                draw_circle(p.x, p.y, self.r, hsla(a.hue, 0.9, 0.5, 70));
+                draw_circle_lines(p.x, p.y, self.r, 2.0, ring);
+                for i in 0..8 {
+                    let ang = i as f32 * std::f32::consts::FRAC_PI_4 + self.angle;
+                    let d = vec2(ang.cos(), ang.sin());
+                    let (from, to) = (p + d * a.radius * 0.6, p + d * a.radius * 1.2);
+                    draw_line(from.x, from.y, to.x, to.y, 2.0, ring);
+                }
+                draw_circle(p.x, p.y, a.radius*0.6, core);
+            }
+            Look::Sniper => {
+                // the sight line brightens as the next shot comes up
+                if let Some(f) = a.fire {
+                    let ready = 1.0 - (self.cool / f.cooldown[0]).clamp(0.0, 1.0);
+                    if ready > 0.4 { draw_line(p.x, p.y, player.x + shake.x, player.y + shake.y, 1.0, hsla(a.hue, 0.9, 0.7, (ready*160.0) as u8)); }
+                }
+                draw_poly(p.x, p.y, 4, self.r*1.3, 0.0, core);
+                draw_poly_lines(p.x, p.y, 4, self.r*1.3+3.0, 0.0, 2.0, ring);
+            }
+            Look::Dart => {
+                let d = facing(self.vel);
+                let side = vec2(-d.y, d.x);
+                draw_triangle(p + d*self.r*1.6, p - d*self.r + side*self.r*0.8, p - d*self.r - side*self.r*0.8, ring);
+            }
+        }
     }
+}
+
+impl sim::Telegraph {
     fn draw(&self, t: f32, shake: Vec2){
-        let hue = match self.kind {0=>0.03,1=>0.93,_=>0.66};
-        let core = hsla(hue + 0.05*(t*2.0).sin(), 0.85, 0.55, 255);
-        let ring = hsla(hue, 0.9, 0.75, 255);
-        draw_circle_lines(self.pos.x+shake.x, self.pos.y+shake.y, self.r+3.0, 2.0, ring);
-        draw_circle(self.pos.x+shake.x, self.pos.y+shake.y, self.r, core);
+        let a = enemies::registry().get(self.kind);
+        let k = self.progress();
+        let flash = if (t*14.0).sin() > 0.0 { 230 } else { 110 };
+        let col = hsla(a.hue, 0.9, 0.7, flash);
+        let edge = clamp_to_view(self.pos);
+        if edge == self.pos {
+            // a portal that tightens onto the spot
+            let p = self.pos + shake;
+            let r = a.radius * (1.0 + 2.0*(1.0 - k));
+            draw_circle(p.x, p.y, a.radius*k, hsla(a.hue, 0.9, 0.5, 70));
+            draw_circle_lines(p.x, p.y, r, 2.0, col);
+            draw_poly_lines(p.x, p.y, 6, r + 6.0, t*120.0, 1.0, hsla(a.hue, 0.9, 0.7, 90));
+        } else {
+            // a chevron pointing in from the edge, with a bar filling up under it
+            let d = (edge - self.pos).normalize_or_zero();
+            let side = vec2(-d.y, d.x);
+            let p = edge + shake;
+            draw_edge_arrow(p + d*6.0, d, 12.0, col);
+            let (l, r) = (p - side*24.0, p + side*24.0);
+            let fill = l + side*48.0*k;
+            draw_line(l.x, l.y, r.x, r.y, 3.0, hsla(a.hue, 0.6, 0.4, 120));
+            draw_line(l.x, l.y, fill.x, fill.y, 3.0, col);
+        }
     }
 }
 
-struct Shard { pos: Vec2, r: f32, t: f32 }
-impl Shard {
-    fn new(pos: Vec2) -> Self { Self { pos, r: SHARD_RADIUS, t: rand_angle() } }
-    fn draw(&self, t: f32, shake: Vec2){
+/// Arrows keep this far inside the view.
+const EDGE_INSET: f32 = 14.0;
+
+fn clamp_to_view(p: Vec2) -> Vec2 {
+    if p.x >= 0.0 && p.y >= 0.0 && p.x <= ARENA.x && p.y <= ARENA.y { return p; }
+    vec2(p.x.clamp(EDGE_INSET, ARENA.x - EDGE_INSET), p.y.clamp(EDGE_INSET, ARENA.y - EDGE_INSET))
+}
+
+/// A triangle at `at` pointing along `dir`.
+fn draw_edge_arrow(at: Vec2, dir: Vec2, size: f32, color: Color){
+    let side = vec2(-dir.y, dir.x);
+    draw_triangle(at + dir*size, at - dir*size*0.5 + side*size*0.7, at - dir*size*0.5 - side*size*0.7, color);
+}
+
+/// Arrows on the edge of the view toward enemies still outside it, fading
+/// with distance.
+fn draw_offscreen(w: &World, alpha: f32){
+    for e in w.enemies.values() {
+        let p = e.prev.lerp(e.pos, alpha);
+        let edge = clamp_to_view(p);
+        if edge == p { continue; }
+        let hue = enemies::registry().get(e.kind).hue;
+        let fade = 1.0 - (p.distance(edge) / 200.0).clamp(0.0, 0.7);
+        draw_edge_arrow(edge, (p - edge).normalize_or_zero(), 8.0, hsla(hue, 0.9, 0.7, (fade*220.0) as u8));
+    }
+}
+
+impl sim::Shard {
+    fn draw(&self, t: f32, shake: Vec2, alpha: f32){
+        let p = self.prev.lerp(self.pos, alpha) + shake;
         let hue = (0.5 + 0.1*(t*2.0 + self.t).sin()).fract();
         let glow = hsla(hue, 0.8, 0.6, 90);
-        draw_circle(self.pos.x+shake.x, self.pos.y+shake.y, self.r*1.9, glow);
-        draw_circle_lines(self.pos.x+shake.x, self.pos.y+shake.y, self.r, 2.0, hsla(hue,0.9,0.75,255));
+        draw_circle(p.x, p.y, self.r*1.9, glow);
+        draw_circle_lines(p.x, p.y, self.r, 2.0, hsla(hue,0.9,0.75,255));
     }
 }
 
-fn rand_angle() -> f32 { thread_rng().gen::<f32>() * std::f32::consts::TAU }
-
 
 struct Star { pos: Vec2, vel: f32, chr: u8, hue: f32 }
 impl Star {
-    fn update(&mut self, dt: f32){ self.pos.x += self.vel*dt; if self.pos.x>screen_width()+10.0 { self.pos.x = -10.0; self.pos.y = thread_rng().gen_range(0.0..screen_height()); } }
+    fn update(&mut self, dt: f32){ self.pos.x += self.vel*dt; if self.pos.x>ARENA.x+10.0 { self.pos.x = -10.0; self.pos.y = thread_rng().gen_range(0.0..ARENA.y); } }
     fn draw(&self){
         let c = hsla(self.hue, 0.3, 0.6, 160);
         draw_circle(self.pos.x, self.pos.y, self.chr as f32 * 0.3 + 0.7, c);
     }
 }
 
-struct Bullet { pos: Vec2, vel: Vec2, r: f32, hostile: bool, life: f32 }
-#[derive(Copy, Clone)]
-enum PowerUpKind { Invuln, Magnet, DoubleDash }
-struct PowerUp { pos: Vec2, kind: PowerUpKind, r: f32, spin: f32 }
-struct Boss { pos: Vec2, r: f32, timer: f32, phase: f32 }
 
 
+/// The profile screen, shown at startup and from the game-over screen.
+struct Picker { names: Vec<String>, sel: usize, edit: Option<Edit>, error: Option<String> }
+
+enum Edit { Create(String), Rename(String), Import(String, SaveData), Delete }
+
 struct Game {
-    player: Player,
-    enemies: Vec<Enemy>,
-    shards: Vec<Shard>,
+    world: World,
     particles: Vec<Particle>,
     textfx: Vec<TextFx>,
-    spawn_timer: f32,
-    shard_timer: f32,
-    score: i32,
     best: i32,
-    combo: f32,
-    combo_t: f32,
+    /// The save failed its integrity check at some point.
+    modified: bool,
+    stats: Stats,
+    stats_open: bool,
     shake: f32,
     paused: bool,
-    over: bool,
     stars: Vec<Star>,
-    enemy_rate_boost: f32,
     fullscreen: bool,
     currency: i32,
     upgrades: Upgrades,
     shop_open: bool,
-    bullets: Vec<Bullet>,
-    powerups: Vec<PowerUp>,
-    powerup_timer: f32,
-    power_invuln: f32,
-    power_magnet: f32,
-    power_ddash: f32,
-    boss: Option<Boss>,
-    next_boss_score: i32,
+    shop_sel: usize,
+    input: Box<dyn InputSource>,
+    frame_input: InputState,
+    dash_queued: bool,
+    pinned_seed: Option<u64>,
+    accumulator: f32,
+    recording: Replay,
+    playback: Option<Playback>,
+    kept_replay: bool,
+    ghost: Option<Ghost>,
+    event_buf: Vec<Event>,
+    notices: Vec<(String, f32)>,
+    quit_armed: bool,
+    /// Root of everything the game writes; profiles live below it.
+    data: PathBuf,
+    profile: Option<String>,
+    /// The active profile's folder, holding its save and replays.
+    dir: PathBuf,
+    /// The profile's save could not be read and must not be written over.
+    locked: bool,
+    picker: Option<Picker>,
+    board: Leaderboard,
+    /// The run's seed was chosen rather than rolled, so it ranks separately.
+    seeded: bool,
+    /// Name being typed for a run that made the leaderboard.
+    name_entry: Option<String>,
+    /// Where the last finished run landed on the leaderboard.
+    placed: Option<usize>,
+    /// Adaptive difficulty is on for the next run, starting at `difficulty`.
+    adaptive: bool,
+    difficulty: f32,
 }
 
 impl Game {
-    fn new() -> Self {
+    fn new(pinned_seed: Option<u64>, data: PathBuf, want_profile: Option<String>) -> Self {
+        let seed = pinned_seed.unwrap_or_else(new_seed);
         let mut g = Self {
-            player: Player::new(),
-            enemies: vec![],
-            shards: vec![],
+            world: World::new(Upgrades::default(), seed),
             particles: vec![],
             textfx: vec![],
-            spawn_timer: ENEMY_SPAWN_START,
-            shard_timer: SHARD_SPAWN_RATE,
-            score: 0,
             best: 0,
-            combo: 0.0,
-            combo_t: 0.0,
+            modified: false,
+            stats: Stats::default(),
+            stats_open: false,
             shake: 0.0,
             paused: false,
-            over: false,
             stars: vec![],
-            enemy_rate_boost: 0.0,
             fullscreen: false,
             currency: 0,
             upgrades: Upgrades::default(),
             shop_open: false,
-            bullets: vec![],
-            powerups: vec![],
-            powerup_timer: 6.0,
-            power_invuln: 0.0,
-            power_magnet: 0.0,
-            power_ddash: 0.0,
-            boss: None,
-            next_boss_score: 200,
+            shop_sel: 0,
+            input: Box::new(Keyboard),
+            frame_input: InputState::default(),
+            dash_queued: false,
+            pinned_seed,
+            accumulator: 0.0,
+            recording: Replay::new(seed, Upgrades::default(), None),
+            playback: None,
+            kept_replay: false,
+            ghost: None,
+            event_buf: vec![],
+            notices: vec![],
+            quit_armed: false,
+            data,
+            profile: None,
+            dir: PathBuf::new(),
+            locked: false,
+            picker: None,
+            board: Leaderboard::default(),
+            seeded: false,
+            name_entry: None,
+            placed: None,
+            adaptive: false,
+            difficulty: 0.0,
         };
         g.init_stars();
-        if let Some((cur, ups, best)) = load_from_disk() {
-            g.currency = cur;
-            g.upgrades = ups;
-            g.best = g.best.max(best);
+        match paths::import_local_save(&g.data) {
+            Ok(true) => g.notify(format!("Imported {} into {}", SAVE_FILE, g.data.display())),
+            Ok(false) => {}
+            Err(e) => g.notify(format!("could not import {}: {}", SAVE_FILE, e)),
+        }
+        match profile::adopt_loose_save(&g.data) {
+            Ok(Some(name)) => g.notify(format!("Your progress now lives in profile {}", name)),
+            Ok(None) => {}
+            Err(e) => g.notify(format!("could not move save into a profile: {}", e)),
+        }
+        let board_path = g.data.join(LEADERBOARD_FILE);
+        match Leaderboard::load(&board_path) {
+            Ok(b) => g.board = b,
+            Err(e) => {
+                g.notify(format!("{}: {}; starting a new one", LEADERBOARD_FILE, e));
+                if let Err(e) = save::keep_copy(&board_path, "corrupt") { g.notify(format!("could not keep the old leaderboard: {}", e)); }
+            }
+        }
+        match want_profile {
+            // the name becomes a path, so it must not be able to step outside `profiles`
+            Some(name) => match profile::check_name(&name) {
+                Ok(()) if profile::dir(&g.data, &name).is_dir() => g.select_profile(name),
+                Ok(()) => { g.notify(format!("no profile named {}", name)); g.open_picker(); }
+                Err(e) => { g.notify(format!("bad profile name {:?}: {}", name, e)); g.open_picker(); }
+            },
+            None => g.open_picker(),
         }
         g
     }
     fn init_stars(&mut self){
         let mut rng = thread_rng();
         self.stars.clear();
-        let n = (screen_width()*screen_height()/15000.0).max(40.0) as usize;
-        for _ in 0..n { self.stars.push(Star{ pos: vec2(rng.gen_range(0.0..screen_width()), rng.gen_range(0.0..screen_height())), vel: rng.gen_range(40.0..140.0), chr: rng.gen_range(1..4), hue: rng.gen::<f32>() }); }
+        let n = (ARENA.x*ARENA.y/15000.0) as usize;
+        for _ in 0..n { self.stars.push(Star{ pos: vec2(rng.gen_range(0.0..ARENA.x), rng.gen_range(0.0..ARENA.y)), vel: rng.gen_range(40.0..140.0), chr: rng.gen_range(1..4), hue: rng.gen::<f32>() }); }
     }
 
-    fn reset_round(&mut self){
-        self.player = Player::new();
-        self.enemies.clear();
-        self.shards.clear();
+    fn reset_round(&mut self, seed: u64){
+        let start = self.adaptive.then_some(self.difficulty);
+        self.world = World::new(self.upgrades.clone(), seed);
+        self.world.adaptive = start.map(Adaptive::new);
+        self.recording = Replay::new(seed, self.upgrades.clone(), start);
+        self.playback = None;
+        self.kept_replay = false;
+        self.name_entry = None;
+        self.placed = None;
+        self.ghost = load_ghost(&self.dir, seed);
         self.particles.clear();
         self.textfx.clear();
-        self.spawn_timer = ENEMY_SPAWN_START;
-        self.shard_timer = SHARD_SPAWN_RATE;
-        self.score = 0;
-        self.combo = 0.0;
-        self.combo_t = 0.0;
         self.shake = 0.0;
         self.paused = false;
-        self.over = false;
-        self.enemy_rate_boost = 0.0;
-        self.bullets.clear();
-        self.powerups.clear();
-        self.powerup_timer = 6.0;
-        self.power_invuln = 0.0;
-        self.power_magnet = 0.0;
-        self.power_ddash = 0.0;
-        self.boss = None;
+        self.dash_queued = false;
+        self.accumulator = 0.0;
         self.init_stars();
     }
 
     fn handle_input(&mut self){
-        if is_key_pressed(KeyCode::Escape) { save_to_disk(self.currency, &self.upgrades, self.best); std::process::exit(0); }
-        if is_key_pressed(KeyCode::P) { self.paused = !self.paused; }
-        if is_key_pressed(KeyCode::R) { self.reset_round(); }
-        if !self.shop_open && !self.over && is_key_pressed(KeyCode::Space) {
-            if self.player.try_dash(self.upgrades.dash_time(), self.upgrades.dash_cd()){
-                self.add_particles(self.player.pos, hsla(0.52,0.8,0.7,200), 40, 400.0);
-                self.shake = self.shake.max(10.0);
-            }
+        if self.picker.is_some() { self.picker_input(); return; }
+        if self.name_entry.is_some() { self.name_input(); return; }
+        let inp = self.input.poll();
+        self.frame_input = inp;
+        if is_key_pressed(KeyCode::Escape) {
+            if self.persist() || self.quit_armed { std::process::exit(0); }
+            self.quit_armed = true;
+            self.notify("Press Esc again to quit without saving".into());
+        }
+        if inp.pause { self.paused = !self.paused; }
+        if is_key_pressed(KeyCode::R) {
+            let retry = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            let seed = if retry { self.world.seed } else { self.pinned_seed.unwrap_or_else(new_seed) };
+            self.seeded = retry || self.pinned_seed.is_some();
+            self.reset_round(seed);
+        }
+        if !self.shop_open && !self.world.over && inp.dash { self.dash_queued = true; }
+        if self.world.over && self.playback.is_none() && !self.kept_replay && is_key_pressed(KeyCode::K) { self.keep_replay(); }
+        if self.world.over && self.playback.is_none() && is_key_pressed(KeyCode::O) { self.open_picker(); }
+        if self.world.over && self.playback.is_none() && is_key_pressed(KeyCode::G) {
+            self.adaptive = !self.adaptive;
+            self.persist();
+        }
+        if is_key_pressed(KeyCode::T) {
+            self.stats_open = !self.stats_open;
+            self.shop_open = false;
+            if !self.world.over { self.paused = self.stats_open; }
         }
 
         if is_key_pressed(KeyCode::F11) {
             self.fullscreen = !self.fullscreen;
             set_fullscreen(self.fullscreen);
-            self.init_stars();
         }
 
-        if is_key_pressed(KeyCode::F6) { request_new_screen_size(1280.0, 720.0); self.init_stars(); }
-        if is_key_pressed(KeyCode::F7) { request_new_screen_size(1600.0, 900.0); self.init_stars(); }
-        if is_key_pressed(KeyCode::F8) { request_new_screen_size(1920.0,1080.0); self.init_stars(); }
-        if is_key_pressed(KeyCode::F9) { request_new_screen_size(2560.0,1440.0); self.init_stars(); }
+        if is_key_pressed(KeyCode::F6) { request_new_screen_size(1280.0, 720.0); }
+        if is_key_pressed(KeyCode::F7) { request_new_screen_size(1600.0, 900.0); }
+        if is_key_pressed(KeyCode::F8) { request_new_screen_size(1920.0,1080.0); }
+        if is_key_pressed(KeyCode::F9) { request_new_screen_size(2560.0,1440.0); }
 
 
-        if !self.over && is_key_pressed(KeyCode::U) {
+        if !self.world.over && self.playback.is_none() && inp.shop {
             self.shop_open = !self.shop_open;
+            self.stats_open = false;
             self.paused = self.shop_open;
         }
-        if !self.over && self.shop_open {
-            if is_key_pressed(KeyCode::Key1) { self.try_buy(1); }
-            if is_key_pressed(KeyCode::Key2) { self.try_buy(2); }
-            if is_key_pressed(KeyCode::Key3) { self.try_buy(3); }
-            if is_key_pressed(KeyCode::Key4) { self.try_buy(4); }
-            if is_key_pressed(KeyCode::Key5) { self.try_buy(5); }
+        if !self.world.over && self.shop_open {
+            if inp.shop_up { self.shop_sel = (self.shop_sel + 4) % 5; }
+            if inp.shop_down { self.shop_sel = (self.shop_sel + 1) % 5; }
+            if inp.shop_buy { self.try_buy(self.shop_sel as u32 + 1); }
+            if let Some(i) = inp.shop_pick { self.shop_sel = i; self.try_buy(i as u32 + 1); }
         }
     }
 
-    fn update(&mut self, dt: f32){
-
-        if self.over {
-            for s in &mut self.stars { s.update(dt); }
-            for p in &mut self.particles { p.update(dt); }
-            self.particles.retain(|p| p.life>0.0 && p.size>0.0);
-            for t in &mut self.textfx { t.update(dt); }
-            self.textfx.retain(|t| t.life>0.0);
-            self.shake = (self.shake - dt*18.0).max(0.0);
-            return;
-        }
-
-
-        if self.power_invuln > 0.0 { self.power_invuln -= dt; }
-        if self.power_magnet > 0.0 { self.power_magnet -= dt; }
-        if self.power_ddash > 0.0 { self.power_ddash -= dt; }
-        self.player.dashes_max = if self.power_ddash > 0.0 { 2 } else { 1 };
-
-        self.player.update(dt, self.upgrades.player_speed());
-        for s in &mut self.stars { s.update(dt); }
-
-        self.enemy_rate_boost += dt*0.03;
-
-        self.spawn_timer -= dt;
-        let rate = (ENEMY_SPAWN_START - self.enemy_rate_boost).max(ENEMY_SPAWN_MIN);
-        if self.spawn_timer <= 0.0 { self.spawn_enemy(); self.spawn_timer = rate; }
-
-
-        if self.boss.is_none() && self.score >= self.next_boss_score {
-            self.boss = Some(Boss{ pos: vec2(screen_width()*0.5, screen_height()*0.35), r: 46.0, timer: 20.0, phase: 0.0 });
-            self.textfx.push(TextFx{ pos: vec2(screen_width()*0.5, 80.0), vel: vec2(0.0,0.0), life: 2.0, text: "BOSS".into(), color: hsla(0.9,0.9,0.8,235)});
-        }
-        if let Some(mut b) = self.boss.take() {
-            b.phase += dt; b.timer -= dt;
-            b.pos.x = screen_width()*0.5 + (b.phase*1.2).sin()* (screen_width()*0.35);
-
-            if (b.phase % 1.6) < dt {
-                for i in 0..16 {
-                    let ang = i as f32 / 16.0 * std::f32::consts::TAU;
-                    let v = vec2(ang.cos(), ang.sin()) * 240.0;
-                    self.bullets.push(Bullet{ pos: b.pos, vel: v, r: 6.0, hostile: true, life: 6.0 });
-                }
-            }
-
-            if (b.phase % 0.6) < dt {
-                let mut dir = self.player.pos - b.pos; if dir.length_squared()>0.0 { dir = dir.normalize(); }
-                self.bullets.push(Bullet{ pos: b.pos, vel: dir * 420.0, r: 5.0, hostile: true, life: 5.0 });
-            }
-
-            let player_hit_boss = self.player.pos.distance(b.pos) <= self.player.r + b.r
-                && !(self.player.invuln>0.0 || self.power_invuln>0.0);
-            if player_hit_boss { self.game_over(); }
-
-            if b.timer <= 0.0 {
-                self.textfx.push(TextFx{ pos: b.pos, vel: vec2(0.0,-40.0), life: 1.2, text: "BOSS CLEARED".into(), color: hsla(0.33,0.9,0.8,235)});
-                self.currency += 50; self.bump_score(50);
-                self.next_boss_score += 250; // do not restore boss -> it despawns
-            } else {
-                self.boss = Some(b);
-            }
-        }
-
-        self.shard_timer -= dt;
-        if self.shard_timer <= 0.0 { self.spawn_shard(); self.shard_timer = SHARD_SPAWN_RATE; }
-
-
-        self.powerup_timer -= dt;
-        if self.powerup_timer <= 0.0 {
-            let k = if thread_rng().gen::<f32>() < 0.34 { PowerUpKind::Invuln } else if thread_rng().gen::<f32>() < 0.5 { PowerUpKind::Magnet } else { PowerUpKind::DoubleDash };
-            let pos = vec2(thread_rng().gen_range(40.0..(screen_width()-40.0)), thread_rng().gen_range(40.0..(screen_height()-40.0)));
-            self.powerups.push(PowerUp{ pos, kind: k, r: 12.0, spin: rand_angle() });
-            self.powerup_timer = thread_rng().gen_range(7.0..13.0);
-        }
-
-        for e in &mut self.enemies { e.update(dt, &self.player, get_time() as f32); }
-
-
-        for e in &mut self.enemies {
-            if e.kind == 4 {
-                e.cool -= dt;
-                if e.cool <= 0.0 {
-                    let mut dir = self.player.pos - e.pos; if dir.length_squared()>0.0 { dir = dir.normalize(); }
-                    self.bullets.push(Bullet{ pos: e.pos, vel: dir * 360.0, r: 5.0, hostile: true, life: 5.0 });
-                    e.cool = thread_rng().gen_range(0.9..1.6);
-                }
-            }
-        }
-
-        let pr = self.player.r; let ppos = self.player.pos;
-
-        for e in &self.enemies {
-            let d = ppos.distance(e.pos);
-            if d>pr && d<NEAR_MISS_DIST && self.player.invuln<=0.0 {
-                if thread_rng().gen::<f32>() < 0.02 {
-                    self.score += NEAR_MISS_BONUS;
-                    self.currency += 1;
-                    self.textfx.push(TextFx{ pos: e.pos, vel: vec2(0.0,-40.0), life: 0.8, text: String::from("near!"), color: hsla(0.1,0.9,0.7,235) });
-                }
-            }
-        }
-
-        let mut keep: Vec<Enemy> = Vec::with_capacity(self.enemies.len());
-        let mut hit_player = false;
-        let drained_enemies: Vec<Enemy> = self.enemies.drain(..).collect();
-        for e in drained_enemies {
-            let d = ppos.distance(e.pos);
-            if d <= pr + e.r {
-                if self.player.invuln>0.0 {
-                    self.add_particles(e.pos, hsla(0.96,0.9,0.7,220), 32, 360.0);
-                    self.bump_score(10);
-                    self.currency += 2;
-                    if thread_rng().gen::<f32>() < 0.5 { self.shards.push(Shard::new(e.pos)); }
-                } else {
-                    hit_player = true;
-                }
-            } else { keep.push(e); }
-        }
-        self.enemies = keep;
-        if hit_player { self.game_over(); }
-
-        let mut kept: Vec<Shard> = Vec::with_capacity(self.shards.len());
-        let drained_shards: Vec<Shard> = self.shards.drain(..).collect();
-        for sh in drained_shards {
-            if ppos.distance(sh.pos) <= pr + sh.r {
-                let bonus = (5.0 * (1.0+self.combo)) as i32;
-                self.bump_score(bonus);
-                let cadd = 3 + self.upgrades.shard_currency_bonus();
-                self.currency += cadd;
-                self.textfx.push(TextFx{ pos: sh.pos, vel: vec2(0.0,-40.0), life: 0.8, text: format!("+{}", bonus), color: hsla(0.55,0.9,0.8,235)});
-                self.add_particles(sh.pos, hsla(0.55,0.9,0.7,200), 22, 280.0);
-            } else {
-                let mut s = sh;
-                let d = ppos - s.pos; let dist2 = d.length_squared();
-                let pull = self.upgrades.magnet_speed() + if self.power_magnet>0.0 { 220.0 } else { 0.0 };
-                let radius = if self.power_magnet>0.0 { 260.0 } else { 180.0 };
-                if dist2 < radius*radius && dist2>0.0 { s.pos += d.normalize()* (pull*dt); }
-                kept.push(s);
-            }
-        }
-        self.shards = kept;
-
-
-        let drained_powerups: Vec<PowerUp> = self.powerups.drain(..).collect();
-        let mut kept_pu: Vec<PowerUp> = Vec::with_capacity(drained_powerups.len());
-        for pu in drained_powerups {
-            if self.player.pos.distance(pu.pos) <= self.player.r + pu.r {
-                match pu.kind {
-                    PowerUpKind::Invuln => { self.power_invuln = 5.0; self.textfx.push(TextFx{ pos: pu.pos, vel: vec2(0.0,-40.0), life: 0.9, text: "INVULN".into(), color: hsla(0.14,0.9,0.8,235)}); }
-                    PowerUpKind::Magnet => { self.power_magnet = 6.0; self.textfx.push(TextFx{ pos: pu.pos, vel: vec2(0.0,-40.0), life: 0.9, text: "MAGNET".into(), color: hsla(0.58,0.9,0.8,235)}); }
-                    PowerUpKind::DoubleDash => { self.power_ddash = 8.0; self.textfx.push(TextFx{ pos: pu.pos, vel: vec2(0.0,-40.0), life: 0.9, text: "DOUBLE DASH".into(), color: hsla(0.33,0.9,0.8,235)}); }
-                }
-                self.add_particles(pu.pos, hsla(0.52,0.9,0.7,220), 28, 300.0);
-            } else {
-                kept_pu.push(pu);
-            }
-        }
-        self.powerups = kept_pu;
-
-        if self.combo_t>0.0 { self.combo_t -= dt; if self.combo_t<=0.0 { self.combo = (self.combo-0.5).max(0.0); self.combo_t = 0.0; } }
-
+    fn tick_input(&mut self) -> InputState {
+        InputState { dash: std::mem::take(&mut self.dash_queued), ..self.frame_input.held() }
+    }
 
-        let drained_bullets: Vec<Bullet> = self.bullets.drain(..).collect();
-        let mut kept_bullets: Vec<Bullet> = Vec::with_capacity(drained_bullets.len());
-        for mut b in drained_bullets {
-            b.pos += b.vel * dt; b.life -= dt;
-            if b.life <= 0.0 { continue; }
-            if b.pos.x < -10.0 || b.pos.x > screen_width()+10.0 || b.pos.y < -10.0 || b.pos.y > screen_height()+10.0 { continue; }
-            if b.hostile {
-                if self.player.pos.distance(b.pos) <= self.player.r + b.r {
-                    if self.player.invuln>0.0 || self.power_invuln>0.0 { self.add_particles(b.pos, hsla(0.0,0.0,1.0,180), 10, 180.0); }
-                    else { self.game_over(); continue; }
+    fn update(&mut self, dt: f32){
+        if !self.world.over && self.picker.is_none() {
+            // cap the catch-up after a hitch so we never spiral
+            self.accumulator += dt.min(0.25);
+            while self.accumulator >= sim::TICK && !self.world.over {
+                let input = match &mut self.playback {
+                    Some(p) => p.next(&mut self.world),
+                    None => {
+                        // a run counts once it is under way, however it ends
+                        if self.recording.ticks == 0 { self.stats.runs += 1; }
+                        let i = self.tick_input();
+                        self.recording.push(&i);
+                        i
+                    }
+                };
+                self.world.update(sim::TICK, &input);
+                if let Some(g) = &mut self.ghost { g.step(sim::TICK); }
+                self.accumulator -= sim::TICK;
+                let earned = self.world.take_credits();
+                if self.playback.is_none() {
+                    self.currency += earned;
+                    self.stats.credits_earned += earned as i64;
+                    self.stats.play_time += sim::TICK as f64;
                 }
+                let mut events = std::mem::take(&mut self.event_buf);
+                events.extend(self.world.drain_events());
+                for ev in events.drain(..) { self.on_event(ev); }
+                self.event_buf = events;
             }
-            kept_bullets.push(b);
         }
-        self.bullets = kept_bullets;
 
+        for s in &mut self.stars { s.update(dt); }
         for p in &mut self.particles { p.update(dt); }
         self.particles.retain(|p| p.life>0.0 && p.size>0.0);
         for t in &mut self.textfx { t.update(dt); }
         self.textfx.retain(|t| t.life>0.0);
         self.shake = (self.shake - dt*18.0).max(0.0);
+        for n in &mut self.notices { n.1 -= dt; }
+        self.notices.retain(|n| n.1 > 0.0);
+    }
+
+    fn on_event(&mut self, ev: Event){
+        if self.playback.is_none() { self.stats.record(&ev); }
+        match ev {
+            Event::Dash(pos) => {
+                self.add_particles(pos, hsla(0.52,0.8,0.7,200), 40, 400.0);
+                self.shake = self.shake.max(10.0);
+            }
+            Event::EnemyKilled(pos) => self.add_particles(pos, hsla(0.96,0.9,0.7,220), 32, 360.0),
+            Event::ShardCollected{ pos, bonus } => {
+                self.textfx.push(TextFx{ pos, vel: vec2(0.0,-40.0), life: 0.8, text: format!("+{}", bonus), color: hsla(0.55,0.9,0.8,235)});
+                self.add_particles(pos, hsla(0.55,0.9,0.7,200), 22, 280.0);
+            }
+            Event::NearMiss(pos) => self.textfx.push(TextFx{ pos, vel: vec2(0.0,-40.0), life: 0.8, text: String::from("near!"), color: hsla(0.1,0.9,0.7,235) }),
+            Event::PowerUp{ pos, kind } => {
+                let (text, color) = match kind {
+                    PowerUpKind::Invuln => ("INVULN", hsla(0.14,0.9,0.8,235)),
+                    PowerUpKind::Magnet => ("MAGNET", hsla(0.58,0.9,0.8,235)),
+                    PowerUpKind::DoubleDash => ("DOUBLE DASH", hsla(0.33,0.9,0.8,235)),
+                };
+                self.textfx.push(TextFx{ pos, vel: vec2(0.0,-40.0), life: 0.9, text: text.into(), color });
+                self.add_particles(pos, hsla(0.52,0.9,0.7,220), 28, 300.0);
+            }
+            Event::BossSpawned => self.textfx.push(TextFx{ pos: vec2(ARENA.x*0.5, 80.0), vel: vec2(0.0,0.0), life: 2.0, text: "BOSS".into(), color: hsla(0.9,0.9,0.8,235)}),
+            Event::WaveStarted { number, name } => self.textfx.push(TextFx{ pos: vec2(ARENA.x*0.5, 110.0), vel: vec2(0.0,-8.0), life: 1.6, text: format!("WAVE {}  {}", number, name), color: hsla(0.55,0.9,0.8,235)}),
+            Event::BossCleared(pos) => self.textfx.push(TextFx{ pos, vel: vec2(0.0,-40.0), life: 1.2, text: "BOSS CLEARED".into(), color: hsla(0.33,0.9,0.8,235)}),
+            Event::BulletBlocked(pos) => self.add_particles(pos, hsla(0.0,0.0,1.0,180), 10, 180.0),
+            Event::GameOver(pos) => self.game_over(pos),
+        }
     }
 
     fn draw(&self){
 
-        clear_background(Color::from_rgba(6, 8, 20, 255));
+        clear_background(BLACK);
+        set_camera(&view::camera());
+        draw_rectangle(0.0, 0.0, ARENA.x, ARENA.y, Color::from_rgba(6, 8, 20, 255));
         let t = get_time() as f32;
-        for i in 0..8 { let y = ((t*0.3 + i as f32).sin()*0.5+0.5) * screen_height(); draw_rectangle(0.0, y, screen_width(), 14.0, hsla(0.66 - i as f32*0.04, 0.25, 0.06, 40)); }
+        for i in 0..8 { let y = ((t*0.3 + i as f32).sin()*0.5+0.5) * ARENA.y; draw_rectangle(0.0, y, ARENA.x, 14.0, hsla(0.66 - i as f32*0.04, 0.25, 0.06, 40)); }
         for s in &self.stars { s.draw(); }
+        if let Some(p) = &self.picker {
+            view::draw_letterbox();
+            self.draw_picker(p);
+            self.draw_notices();
+            return;
+        }
 
         let sv = if self.shake>0.0 { vec2(rand_f(-self.shake, self.shake), rand_f(-self.shake, self.shake)) } else { Vec2::ZERO };
-
-        for sh in &self.shards { sh.draw(t, sv); }
-        for e in &self.enemies { e.draw(t, sv); }
-        self.player.draw(t, sv);
+        let w = &self.world;
+        let alpha = if w.over { 1.0 } else { self.accumulator / sim::TICK };
+
+        for sh in w.shards.values() { sh.draw(t, sv, alpha); }
+        for e in w.enemies.values() { e.draw(t, sv, alpha, w.player.pos); }
+        for tg in &w.telegraphs { tg.draw(t, sv); }
+        draw_offscreen(w, alpha);
+        if let Some(g) = &self.ghost { if !g.world.over { g.world.player.draw_ghost(sv, alpha); } }
+        w.player.draw(t, sv, alpha);
         for p in &self.particles { p.draw(sv); }
         for tf in &self.textfx { draw_text(&tf.text, tf.pos.x+sv.x, tf.pos.y+sv.y, 24.0, tf.color); }
 
-        for pu in &self.powerups {
+        for pu in w.powerups.values() {
             let col = match pu.kind { PowerUpKind::Invuln => hsla(0.14,0.9,0.7,220), PowerUpKind::Magnet => hsla(0.58,0.9,0.7,220), PowerUpKind::DoubleDash => hsla(0.33,0.9,0.7,220) };
             draw_circle(pu.pos.x+sv.x, pu.pos.y+sv.y, pu.r*1.8, Color::from_rgba(255,255,255,30));
             draw_circle_lines(pu.pos.x+sv.x, pu.pos.y+sv.y, pu.r, 2.0, col);
             draw_circle(pu.pos.x+sv.x, pu.pos.y+sv.y, pu.r*0.6, col);
         }
-        for b in &self.bullets { draw_circle(b.pos.x+sv.x, b.pos.y+sv.y, b.r, hsla(0.95,0.9,0.7,235)); }
-        if let Some(b) = &self.boss {
-            draw_circle(b.pos.x+sv.x, b.pos.y+sv.y, b.r*1.8, Color::from_rgba(255,140,160,30));
-            draw_circle_lines(b.pos.x+sv.x, b.pos.y+sv.y, b.r, 3.0, hsla(0.93,0.9,0.7,235));
-            draw_circle(b.pos.x+sv.x, b.pos.y+sv.y, b.r*0.6, hsla(0.93,0.7,0.6,235));
+        for b in w.bullets.values() { let p = b.prev.lerp(b.pos, alpha) + sv; draw_circle(p.x, p.y, b.r, hsla(0.95,0.9,0.7,235)); }
+        if let Some(b) = &w.boss {
+            let p = b.prev.lerp(b.pos, alpha) + sv;
+            draw_circle(p.x, p.y, b.r*1.8, Color::from_rgba(255,140,160,30));
+            draw_circle_lines(p.x, p.y, b.r, 3.0, hsla(0.93,0.9,0.7,235));
+            draw_circle(p.x, p.y, b.r*0.6, hsla(0.93,0.7,0.6,235));
         }
+        view::draw_letterbox();
 
         let hud = format!(
             "Credits: {}   Score: {}   Combo: x{:.1}   [INV {:.0}s] [MAG {:.0}s] [DD {:.0}s]",
-            self.currency, self.score, 1.0+self.combo,
-            self.power_invuln.max(0.0), self.power_magnet.max(0.0), self.power_ddash.max(0.0)
+            self.currency, w.score, 1.0+w.combo,
+            w.power_invuln.max(0.0), w.power_magnet.max(0.0), w.power_ddash.max(0.0)
         );
         draw_text(&hud, 16.0, 24.0, 28.0, Color::from_rgba(230,240,250,255));
+        if let Some(g) = &self.ghost {
+            let delta = w.score - g.world.score;
+            let (text, col) = if g.world.over && delta > 0 { (format!("Ghost beaten by {}", delta), hsla(0.33,0.9,0.7,235)) }
+                else if delta >= 0 { (format!("Ghost +{}", delta), hsla(0.33,0.9,0.7,235)) }
+                else { (format!("Ghost {}", delta), hsla(0.0,0.9,0.7,235)) };
+            draw_text(&text, 16.0, 52.0, 24.0, col);
+        }
 
-        if self.player.dash_cd>0.0 {
-            let w = 160.0; let x = screen_width()-24.0-w; let y = 16.0;
+        if w.player.dash_cd>0.0 {
+            let w = 160.0; let x = ARENA.x-24.0-w; let y = 16.0;
             draw_rectangle_lines(x, y, w, 10.0, 1.0, Color::from_rgba(70,90,120,220));
-            let cdw = w * (1.0 - (self.player.dash_cd / self.upgrades.dash_cd()).clamp(0.0,1.0));
+            let cdw = w * (1.0 - (self.world.player.dash_cd / self.upgrades.dash_cd()).clamp(0.0,1.0));
             draw_rectangle(x, y, cdw, 10.0, Color::from_rgba(120,200,255,255));
             draw_text("dash", x, y+20.0, 20.0, Color::from_rgba(150,200,255,220));
         }
 
         if self.paused { self.center_msg("Paused — press P to resume", Color::from_rgba(220,220,240,255)); }
-        if self.over { self.center_msg(&format!("Game Over  •  Score {}  •  Best {}\nPress R to restart", self.score, self.best), Color::from_rgba(250,210,210,255)); }
+        if w.over && self.playback.is_some() { self.center_msg(&format!("Replay finished  •  Score {}\nSeed {}\nR new run  •  Shift+R play this seed", w.score, w.seed), Color::from_rgba(210,220,250,255)); }
+        else if w.over { self.draw_game_over(); }
+        else if self.playback.is_some() { draw_text("REPLAY", ARENA.x-120.0, ARENA.y-20.0, 28.0, Color::from_rgba(210,220,250,220)); }
         if self.shop_open { self.draw_shop(); }
+        if self.stats_open { self.draw_stats(); }
+        self.draw_notices();
+    }
+
+    fn draw_notices(&self){
+        for (i, (text, t)) in self.notices.iter().rev().enumerate() {
+            let a = (t.min(1.0) * 235.0) as u8;
+            draw_text(text, 16.0, ARENA.y - 16.0 - i as f32 * 26.0, 24.0, Color::from_rgba(255, 200, 120, a));
+        }
+    }
+
+    fn draw_game_over(&self){
+        let w = &self.world;
+        let (x, wd) = (ARENA.x*0.5 - 450.0, 900.0);
+        let white = Color::from_rgba(230,240,250,255);
+        let grey = Color::from_rgba(160,180,210,220);
+        draw_rectangle(x, 50.0, wd, 620.0, Color::from_rgba(20, 24, 44, 220));
+        let centered = |text: &str, y: f32, size: u16, color: Color| {
+            let m = measure_text(text, None, size, 1.0);
+            draw_text(text, ARENA.x*0.5 - m.width*0.5, y, size as f32, color);
+        };
+        centered(&format!("Game Over  •  Score {}", w.score), 110.0, 64, Color::from_rgba(250,210,210,255));
+        let who = format!("{}{}", self.profile.as_deref().unwrap_or(""), if self.modified { " (modified, not ranked)" } else { "" });
+        centered(&format!("Seed {}  •  {}  •  personal best {}", w.seed, who, self.best), 146.0, 26, grey);
+        let (s, c) = (&w.score_by, &w.credits_by);
+        centered(&format!("Score  shards {} (+{} combo)  •  kills {}  •  near-miss {}  •  boss {}", s.shards, w.combo_bonus, s.kills, s.near_miss, s.boss), 180.0, 24, white);
+        centered(&format!("Credits {}  shards {}  •  kills {}  •  near-miss {}  •  boss {}", w.credits, c.shards, c.kills, c.near_miss, c.boss), 206.0, 24, white);
+
+        let cols = [x+30.0, x+80.0, x+400.0, x+520.0, x+630.0, x+770.0];
+        for (c, h) in cols.iter().zip(["#", "Name", "Score", "Time", "Seed", "Date"]) { draw_text(h, *c, 246.0, 24.0, grey); }
+        let top = self.board.top(self.mode());
+        for (i, e) in top.iter().enumerate() {
+            let y = 276.0 + i as f32 * 28.0;
+            if Some(i) == self.placed { draw_rectangle(x+16.0, y-21.0, wd-32.0, 28.0, Color::from_rgba(60, 80, 130, 160)); }
+            let cells = [format!("{}", i+1), e.name.clone(), e.score.to_string(), format!("{:.0}s", e.duration), e.seed.to_string(), e.date.chars().take(10).collect()];
+            for (c, text) in cols.iter().zip(cells) { draw_text(&text, *c, y, 24.0, white); }
+        }
+        if top.is_empty() { draw_text("No runs on the board yet", cols[1], 276.0, 24.0, grey); }
+        let mode = if self.seeded { "chosen seeds" } else { "random seeds" };
+        let adaptive = if w.adaptive.is_some() { ", adaptive difficulty" } else { "" };
+        draw_text(&format!("Top {} — {}{}", leaderboard::SIZE, mode, adaptive), x+30.0, 560.0, 22.0, grey);
+        if let Some(a) = w.adaptive.as_ref().map(Adaptive::adjustment) {
+            let text = format!("Difficulty {} average ({} to {})", adaptive::percent(a.average), adaptive::percent(a.start), adaptive::percent(a.end));
+            let m = measure_text(&text, None, 22, 1.0);
+            draw_text(&text, x+wd-30.0-m.width, 560.0, 22.0, grey);
+        }
+
+        if let Some(name) = &self.name_entry {
+            centered(&format!("New high score! Your name: {}_   (Enter to save)", name), 604.0, 30, hsla(0.14,0.9,0.75,255));
+        } else {
+            let next = if self.adaptive { format!("on, next run starts at {}", adaptive::percent(self.difficulty)) } else { "off".into() };
+            centered(&format!("G adaptive difficulty: {}", next), 614.0, 22, grey);
+            let keep = if self.kept_replay { "replay kept" } else { "K keep replay" };
+            centered(&format!("R restart  •  Shift+R retry seed  •  {}  •  O switch profile  •  T stats", keep), 648.0, 26, white);
+        }
     }
 
     fn center_msg(&self, text: &str, color: Color){
         let lines: Vec<&str> = text.split('\n').collect();
         let total_h = lines.len() as f32 * 64.0 + (lines.len().saturating_sub(1) as f32)*8.0;
-        let mut y = screen_height()/2.0 - total_h/2.0;
+        let mut y = ARENA.y/2.0 - total_h/2.0;
         for line in lines {
             let m = measure_text(line, None, 64, 1.0);
-            draw_text(line, screen_width()/2.0 - m.width/2.0, y, 64.0, color);
+            draw_text(line, ARENA.x/2.0 - m.width/2.0, y, 64.0, color);
             y += 72.0;
         }
     }
@@ -666,40 +736,28 @@ impl Game {
         }
     }
 
-    fn spawn_enemy(&mut self){
-        let mut rng = thread_rng();
-        let side = rng.gen_range(0..4);
-        let m = 24.0;
-        let pos = match side {
-            0 => vec2(rng.gen_range(0.0..screen_width()), -m),
-            1 => vec2(rng.gen_range(0.0..screen_width()), screen_height()+m),
-            2 => vec2(-m, rng.gen_range(0.0..screen_height())),
-            _ => vec2(screen_width()+m, rng.gen_range(0.0..screen_height())),
-        };
-        let kind = rng.gen_range(0..=2);
-        self.enemies.push(Enemy::new(pos, kind));
-    }
-
-    fn spawn_shard(&mut self){
-        let mut rng = thread_rng();
-        let pos = vec2(
-            rng.gen_range(40.0..(screen_width() - 40.0)),
-            rng.gen_range(40.0..(screen_height() - 40.0)),
-        );
-        self.shards.push(Shard::new(pos));
-    }
-
-    fn bump_score(&mut self, base: i32){
-        self.score += base;
-        self.combo += COMBO_INC; self.combo_t = COMBO_TIME;
-    }
-
-    fn game_over(&mut self){
-        self.shake = 20.0; self.add_particles(self.player.pos, hsla(0.0,0.9,0.7,230), 80, 420.0);
-        self.best = self.best.max(self.score); self.over = true;
+    fn game_over(&mut self, pos: Vec2){
+        self.shake = 20.0; self.add_particles(pos, hsla(0.0,0.9,0.7,230), 80, 420.0);
+        if self.playback.is_some() { return; }
+        self.recording.score = self.world.score;
+        if let Some((cause, _)) = self.world.death { self.stats.record_death(cause); }
+        if let Err(e) = self.recording.save(&self.dir.join(REPLAY_DIR).join("last.json")) { self.notify(format!("could not save replay: {}", e)); }
+        // a rolled seed rarely comes round again, so only chosen ones keep a best run
+        let best_on_seed = self.ghost.as_ref().map_or(i32::MIN, |g| g.final_score);
+        if self.seeded && self.world.score > best_on_seed {
+            if let Err(e) = self.recording.save(&replay::best_path(&self.dir, self.world.seed)) { self.notify(format!("could not save best replay: {}", e)); }
+        }
+        if let Err(e) = history::append(&self.dir.join(HISTORY_FILE), &RunRecord::new(&self.world)) { self.notify(format!("could not write run history: {}", e)); }
+        if let Some(a) = &self.world.adaptive { self.difficulty = adaptive::next_start(a.start, self.world.time); }
+        if !self.modified && self.board.rank(self.mode(), self.world.score).is_some() {
+            // drop whatever was typed during the run
+            while get_char_pressed().is_some() {}
+            self.name_entry = Some(self.profile.clone().unwrap_or_default());
+        }
+        self.best = self.best.max(self.world.score);
         self.shop_open = false;
         self.paused = false;
-        save_to_disk(self.currency, &self.upgrades, self.best);
+        self.persist();
     }
 
     fn try_buy(&mut self, idx: u32){
@@ -713,6 +771,7 @@ impl Game {
         };
         if self.currency < cost { return; }
         self.currency -= cost;
+        self.stats.credits_spent += cost as i64;
         match apply {
             0 => { self.upgrades.speed += 1; },
             1 => { self.upgrades.dash_cd += 1; },
@@ -721,18 +780,283 @@ impl Game {
             4 => { self.upgrades.magnet += 1; },
             _ => {}
         }
-        self.textfx.push(TextFx{ pos: self.player.pos, vel: vec2(0.0,-40.0), life: 0.9, text: format!("{}! {}", name, effect_text), color: hsla(0.33,0.9,0.8,235)});
-        save_to_disk(self.currency, &self.upgrades, self.best);
+        self.world.upgrades = self.upgrades.clone();
+        self.recording.change(Change::Upgrades(self.upgrades.clone()));
+        self.textfx.push(TextFx{ pos: self.world.player.pos, vel: vec2(0.0,-40.0), life: 0.9, text: format!("{}! {}", name, effect_text), color: hsla(0.33,0.9,0.8,235)});
+        self.persist();
+    }
+
+    fn load_save(&mut self){
+        let path = self.dir.join(SAVE_FILE);
+        let rec = save::load_or_recover(&path);
+        self.locked = rec.locked;
+        for n in rec.notices { self.notify(n); }
+        if let Some(d) = rec.data {
+            self.currency = d.currency;
+            self.upgrades = d.upgrades;
+            self.best = d.best;
+            self.modified = d.modified;
+            self.stats = d.stats;
+            self.adaptive = d.adaptive;
+            self.difficulty = d.difficulty;
+        }
+        if rec.rewrite {
+            // keep the file we migrated or recovered from in case that went wrong
+            if path.exists() { if let Err(e) = save::keep_copy(&path, "old") { self.notify(format!("could not back up old save: {}", e)); } }
+            self.persist();
+        }
+    }
+
+    fn save_data(&self) -> SaveData {
+        SaveData { currency: self.currency, upgrades: self.upgrades.clone(), best: self.best, modified: self.modified, stats: self.stats.clone(), adaptive: self.adaptive, difficulty: self.difficulty }
+    }
+
+    /// Writes the profile, telling the player if it did not stick. A locked
+    /// profile was already reported when it loaded.
+    fn persist(&mut self) -> bool {
+        if self.profile.is_none() { return true; }
+        if self.locked { return false; }
+        match save::save(&self.dir.join(SAVE_FILE), &self.save_data()) {
+            Ok(()) => true,
+            Err(e) => { self.notify(format!("Progress NOT saved: {}", e)); false }
+        }
+    }
+
+    fn notify(&mut self, text: String){
+        eprintln!("{}", text);
+        self.notices.push((text, NOTICE_TIME));
+    }
+
+    /// The leaderboard the current run ranks on. Adaptive runs face different
+    /// odds, so they rank apart.
+    fn mode(&self) -> &'static str {
+        match (self.seeded, self.world.adaptive.is_some()) {
+            (false, false) => "standard",
+            (true, false) => "seeded",
+            (false, true) => "standard-adaptive",
+            (true, true) => "seeded-adaptive",
+        }
+    }
+
+    fn name_input(&mut self){
+        let Some(name) = &mut self.name_entry else { return };
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() && name.chars().count() < profile::MAX_NAME { name.push(c); }
+        }
+        if is_key_pressed(KeyCode::Backspace) { name.pop(); }
+        // Esc keeps the suggested name rather than throwing the score away
+        let done = is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) || is_key_pressed(KeyCode::Escape);
+        if !done { return; }
+        let profile = self.profile.clone().unwrap_or_default();
+        let typed = self.name_entry.take().unwrap_or_default();
+        let name = if typed.trim().is_empty() { profile.clone() } else { typed.trim().to_string() };
+        let w = &self.world;
+        let entry = Entry { name, profile, score: w.score, date: history::utc_string(history::now()), duration: w.time, seed: w.seed };
+        let mode = self.mode();
+        self.placed = self.board.insert(mode, entry);
+        if let Err(e) = self.board.save(&self.data.join(LEADERBOARD_FILE)) { self.notify(format!("could not save the leaderboard: {}", e)); }
+    }
+
+    fn open_picker(&mut self){
+        let names = profile::list(&self.data).unwrap_or_else(|e| { self.notify(format!("could not list profiles: {}", e)); vec![] });
+        let current = self.profile.clone().or_else(|| profile::last(&self.data));
+        let sel = current.and_then(|c| names.iter().position(|n| *n == c)).unwrap_or(0);
+        let edit = if names.is_empty() { Some(Edit::Create(profile::DEFAULT_NAME.into())) } else { None };
+        self.picker = Some(Picker { names, sel, edit, error: None });
+        self.shop_open = false;
+        self.stats_open = false;
+    }
+
+    /// Loads `name` as the active profile and starts a fresh run with it.
+    fn select_profile(&mut self, name: String){
+        profile::set_last(&self.data, &name);
+        self.dir = profile::dir(&self.data, &name);
+        self.profile = Some(name);
+        self.currency = 0;
+        self.upgrades = Upgrades::default();
+        self.best = 0;
+        self.modified = false;
+        self.stats = Stats::default();
+        self.stats_open = false;
+        self.adaptive = false;
+        self.difficulty = 0.0;
+        self.load_save();
+        self.picker = None;
+        // a replay given on the command line waits for the pick, then plays
+        if self.playback.is_none() {
+            self.seeded = self.pinned_seed.is_some();
+            self.reset_round(self.pinned_seed.unwrap_or_else(new_seed));
+        }
+    }
+
+    fn picker_input(&mut self){
+        let Some(mut p) = self.picker.take() else { return };
+        let enter = is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter);
+        let chosen = p.names.get(p.sel).cloned();
+        match &mut p.edit {
+            Some(Edit::Create(text) | Edit::Rename(text) | Edit::Import(text, _)) => {
+                while let Some(c) = get_char_pressed() {
+                    if !c.is_control() && text.chars().count() < profile::MAX_NAME { text.push(c); }
+                }
+                if is_key_pressed(KeyCode::Backspace) { text.pop(); }
+                if is_key_pressed(KeyCode::Escape) { p.edit = None; p.error = None; }
+                else if enter {
+                    let text = text.clone();
+                    let res = match (&p.edit, &chosen) {
+                        (Some(Edit::Rename(_)), Some(old)) => profile::rename(&self.data, old, &text),
+                        (Some(Edit::Import(_, data)), _) => profile::create(&self.data, &text).and_then(|()| {
+                            save::save(&profile::dir(&self.data, &text).join(SAVE_FILE), data).map_err(|e| e.to_string())
+                        }),
+                        _ => profile::create(&self.data, &text),
+                    };
+                    match res {
+                        Ok(()) => {
+                            if matches!(p.edit, Some(Edit::Rename(_))) && self.profile == chosen {
+                                self.dir = profile::dir(&self.data, &text);
+                                self.profile = Some(text.clone());
+                            }
+                            p.names = profile::list(&self.data).unwrap_or_default();
+                            p.sel = p.names.iter().position(|n| *n == text).unwrap_or(0);
+                            p.edit = None;
+                            p.error = None;
+                        }
+                        Err(e) => p.error = Some(e),
+                    }
+                }
+            }
+            Some(Edit::Delete) => {
+                if is_key_pressed(KeyCode::Y) {
+                    if let Some(name) = &chosen {
+                        match profile::delete(&self.data, name) {
+                            Ok(()) => {
+                                if self.profile == chosen { self.profile = None; self.dir = PathBuf::new(); }
+                                p.names.retain(|n| n != name);
+                                p.sel = p.sel.min(p.names.len().saturating_sub(1));
+                                p.error = None;
+                            }
+                            Err(e) => p.error = Some(e),
+                        }
+                    }
+                    p.edit = None;
+                } else if is_key_pressed(KeyCode::N) || is_key_pressed(KeyCode::Escape) { p.edit = None; }
+            }
+            None => {
+                let n = p.names.len().max(1);
+                if is_key_pressed(KeyCode::Up) { p.sel = (p.sel + n - 1) % n; }
+                if is_key_pressed(KeyCode::Down) { p.sel = (p.sel + 1) % n; }
+                if is_key_pressed(KeyCode::N) { p.edit = Some(Edit::Create(String::new())); p.error = None; }
+                if is_key_pressed(KeyCode::V) {
+                    let code = macroquad::miniquad::window::clipboard_get().unwrap_or_default();
+                    match save::import_code(&code) {
+                        Ok(l) => {
+                            for w in l.warnings { self.notify(format!("imported code: {}", w)); }
+                            p.edit = Some(Edit::Import("Imported".into(), l.data));
+                            p.error = None;
+                        }
+                        Err(e) => p.error = Some(format!("clipboard: {}", e)),
+                    }
+                }
+                if let Some(name) = &chosen {
+                    if is_key_pressed(KeyCode::E) || is_key_pressed(KeyCode::F2) { p.edit = Some(Edit::Rename(name.clone())); p.error = None; }
+                    if is_key_pressed(KeyCode::Delete) || is_key_pressed(KeyCode::X) { p.edit = Some(Edit::Delete); }
+                    if is_key_pressed(KeyCode::C) { self.copy_code(name); }
+                    if enter { self.select_profile(name.clone()); return; }
+                }
+                if is_key_pressed(KeyCode::Escape) {
+                    // back to the game if one is loaded, otherwise there is nothing to return to
+                    if self.profile.is_some() { return; }
+                    std::process::exit(0);
+                }
+                // the key that opened a text field also queued its character
+                while get_char_pressed().is_some() {}
+            }
+        }
+        self.picker = Some(p);
+    }
+
+    /// Puts a profile's export code on the clipboard.
+    fn copy_code(&mut self, name: &str){
+        let path = profile::dir(&self.data, name).join(SAVE_FILE);
+        match save::load(&path) {
+            Ok(l) => {
+                let data = l.map(|l| l.data).unwrap_or_default();
+                macroquad::miniquad::window::clipboard_set(&save::export_code(&data));
+                self.notify(format!("Copied the code for {} to the clipboard", name));
+            }
+            Err(e) => self.notify(format!("{}: {}", name, e)),
+        }
+    }
+
+    fn draw_picker(&self, p: &Picker){
+        let (x, y, w, h) = (ARENA.x*0.5 - 360.0, 80.0, 720.0, 520.0);
+        let white = Color::from_rgba(230,240,250,255);
+        let grey = Color::from_rgba(160,180,210,220);
+        draw_rectangle(x, y, w, h, Color::from_rgba(20, 24, 44, 240));
+        draw_rectangle_lines(x, y, w, h, 2.0, Color::from_rgba(120, 150, 200, 200));
+        draw_text("PROFILES", x+20.0, y+40.0, 28.0, white);
+        let mut yy = y + 90.0;
+        if p.names.is_empty() { draw_text("No profiles yet", x+24.0, yy, 26.0, grey); }
+        let first = p.sel.saturating_sub(7);
+        for (i, name) in p.names.iter().enumerate().skip(first).take(8) {
+            if i == p.sel { draw_rectangle(x+12.0, yy-28.0, w-24.0, 38.0, Color::from_rgba(60, 80, 130, 160)); }
+            let current = if self.profile.as_deref() == Some(name.as_str()) { "  (current)" } else { "" };
+            draw_text(&format!("{}{}", name, current), x+24.0, yy, 26.0, white);
+            yy += 44.0;
+        }
+        let prompt = match &p.edit {
+            Some(Edit::Create(t)) => format!("New profile: {}_", t),
+            Some(Edit::Rename(t)) => format!("Rename to: {}_", t),
+            Some(Edit::Import(t, d)) => format!("Import ({} credits, best {}) as: {}_", d.currency, d.best, t),
+            Some(Edit::Delete) => format!("Delete {} and all its progress?  Y / N", p.names.get(p.sel).map_or("", |n| n.as_str())),
+            None => String::new(),
+        };
+        draw_text(&prompt, x+24.0, y+h-112.0, 26.0, Color::from_rgba(200,230,255,230));
+        if let Some(e) = &p.error { draw_text(e, x+24.0, y+h-82.0, 24.0, hsla(0.0,0.9,0.7,235)); }
+        let help = if p.edit.is_some() { ["Enter confirm  •  Esc cancel", ""] }
+            else { ["Enter play  •  N new  •  E rename  •  X delete  •  Esc back", "C copy profile code  •  V paste a code as a new profile"] };
+        draw_text(help[0], x+24.0, y+h-46.0, 24.0, grey);
+        draw_text(help[1], x+24.0, y+h-20.0, 24.0, grey);
+    }
+
+    fn start_playback(&mut self, r: &Replay){
+        self.reset_round(r.seed);
+        self.world = r.world();
+        self.playback = Some(Playback::new(r));
+        self.ghost = None;
+    }
+
+    fn keep_replay(&mut self){
+        let path = self.dir.join(REPLAY_DIR).join(format!("{}-{}.json", self.recording.seed, self.recording.score));
+        match self.recording.save(&path) {
+            Ok(()) => self.kept_replay = true,
+            Err(e) => self.notify(format!("could not keep replay: {}", e)),
+        }
+    }
+
+    fn draw_stats(&self){
+        let (x, y, w, h) = (ARENA.x*0.5 - 360.0, 80.0, 720.0, 520.0);
+        draw_rectangle(x, y, w, h, Color::from_rgba(20, 24, 44, 240));
+        draw_rectangle_lines(x, y, w, h, 2.0, Color::from_rgba(120, 150, 200, 200));
+        let title = format!("LIFETIME STATS — {}   (T to close)", self.profile.as_deref().unwrap_or(""));
+        draw_text(&title, x+20.0, y+40.0, 28.0, Color::from_rgba(230,240,250,255));
+        let mut yy = y + 90.0;
+        for (label, value) in self.stats.rows() {
+            draw_text(label, x+24.0, yy, 24.0, Color::from_rgba(160,180,210,220));
+            let m = measure_text(&value, None, 24, 1.0);
+            draw_text(&value, x+w-24.0-m.width, yy, 24.0, Color::from_rgba(230,240,250,255));
+            yy += 40.0;
+        }
+        draw_text(&format!("Best score: {}", self.best), x+24.0, y+h-20.0, 26.0, Color::from_rgba(200,230,255,230));
     }
 
     fn draw_shop(&self){
-        let x = screen_width()*0.5 - 360.0;
+        let x = ARENA.x*0.5 - 360.0;
         let y = 80.0;
         let w = 720.0;
         let h = 420.0;
         draw_rectangle(x, y, w, h, Color::from_rgba(20, 24, 44, 240));
         draw_rectangle_lines(x, y, w, h, 2.0, Color::from_rgba(120, 150, 200, 200));
-        draw_text("UPGRADES — press 1–5 to buy, U to close", x+20.0, y+40.0, 28.0, Color::from_rgba(230,240,250,255));
+        draw_text("UPGRADES — Up/Down + Enter or 1–5 to buy, U to close", x+20.0, y+40.0, 28.0, Color::from_rgba(230,240,250,255));
         let mut yy = y + 90.0;
         let line_h = 44.0;
         let white = Color::from_rgba(230,240,250,255);
@@ -747,6 +1071,7 @@ impl Game {
         ];
         for (i, line) in lines.iter().enumerate() {
             let color = if (i as i32) % 2 == 0 { white } else { grey };
+            if i == self.shop_sel { draw_rectangle(x+12.0, yy-28.0, w-24.0, 38.0, Color::from_rgba(60, 80, 130, 160)); }
             draw_text(line, x+24.0, yy, 26.0, color);
             yy += line_h;
         }
@@ -755,4 +1080,4 @@ impl Game {
 }
 
 
-fn rand_f(a: f32, b: f32) -> f32 { thread_rng().gen_range(a..b) }
\ No newline at end of file
+fn rand_f(a: f32, b: f32) -> f32 { thread_rng().gen_range(a..b) }
@@ -0,0 +1,56 @@
+// Where the game keeps its files. Saves and replays live in the per-user data
+// directory so a profile does not depend on the folder the game was launched
+// from; `--data-dir` or `NEON_RUSH_DATA_DIR` point it somewhere else.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::profile;
+use crate::save::{self, SAVE_FILE};
+
+const APP_DIR: &str = "neon_rush";
+const DATA_DIR_VAR: &str = "NEON_RUSH_DATA_DIR";
+
+/// The `--data-dir` flag wins over the environment, which wins over the
+/// platform default. Falls back to the working directory if no home is known.
+pub fn data_dir(flag: Option<String>) -> PathBuf {
+    if let Some(d) = flag.filter(|d| !d.is_empty()) { return d.into(); }
+    if let Some(d) = env_dir(DATA_DIR_VAR) { return d; }
+    platform_dir().map_or_else(|| PathBuf::from("."), |d| d.join(APP_DIR))
+}
+
+#[cfg(target_os = "windows")]
+fn platform_dir() -> Option<PathBuf> { env_dir("APPDATA") }
+
+#[cfg(target_os = "macos")]
+fn platform_dir() -> Option<PathBuf> { env_dir("HOME").map(|h| h.join("Library").join("Application Support")) }
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_dir() -> Option<PathBuf> {
+    // the XDG spec says relative values are to be ignored
+    env_dir("XDG_DATA_HOME").filter(|d| d.is_absolute())
+        .or_else(|| env_dir("HOME").map(|h| h.join(".local").join("share")))
+}
+
+fn env_dir(var: &str) -> Option<PathBuf> { env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from) }
+
+/// Moves a save left in the working directory by older builds into `dir`,
+/// unless `dir` already has one, for `profile::adopt_loose_save` to give a
+/// profile. The original is renamed to `.imported` so this only ever happens
+/// once. Returns whether anything was imported.
+pub fn import_local_save(dir: &Path) -> io::Result<bool> {
+    let local = Path::new(SAVE_FILE);
+    let dest = dir.join(SAVE_FILE);
+    // when `dir` is the working directory `dest` is `local` and already exists
+    if !local.is_file() || dest.exists() { return Ok(false); }
+    let first = profile::first_run(dir);
+    fs::create_dir_all(dir)?;
+    fs::copy(local, &dest)?;
+    // older builds wrote unsigned saves; they can be trusted only while
+    // migrating to profiles, not when one is dropped in later
+    if first { save::sign_unsigned(&dest)?; }
+    fs::rename(local, format!("{}.imported", SAVE_FILE))?;
+    Ok(true)
+}
@@ -0,0 +1,91 @@
+// Generational entity storage. Removed slots go on a free list and are reused,
+// so steady-state play does not allocate, and a `Handle` to a removed entity
+// can never alias whatever later moves into its slot.
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Handle { index: u32, gen: u32 }
+
+struct Slot<T> { gen: u32, value: Option<T> }
+
+pub struct Pool<T> { slots: Vec<Slot<T>>, free: Vec<u32> }
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self { Self { slots: vec![], free: vec![] } }
+}
+
+impl<T> Pool<T> {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.value = Some(value);
+            return Handle { index, gen: slot.gen };
+        }
+        self.slots.push(Slot { gen: 0, value: Some(value) });
+        Handle { index: self.slots.len() as u32 - 1, gen: 0 }
+    }
+
+    pub fn remove(&mut self, h: Handle) -> Option<T> {
+        let slot = self.slots.get_mut(h.index as usize)?;
+        if slot.gen != h.gen { return None; }
+        let value = slot.value.take()?;
+        slot.gen = slot.gen.wrapping_add(1);
+        self.free.push(h.index);
+        Some(value)
+    }
+
+    pub fn get(&self, h: Handle) -> Option<&T> {
+        self.slots.get(h.index as usize).filter(|s| s.gen == h.gen)?.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, h: Handle) -> Option<&mut T> {
+        self.slots.get_mut(h.index as usize).filter(|s| s.gen == h.gen)?.value.as_mut()
+    }
+
+    /// Live entities with their handles, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, s)| s.value.as_ref().map(|v| (Handle { index: i as u32, gen: s.gen }, v)))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> { self.slots.iter().filter_map(|s| s.value.as_ref()) }
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> { self.slots.iter_mut().filter_map(|s| s.value.as_mut()) }
+
+    /// Keeps only the entities for which `keep` returns true.
+    pub fn retain(&mut self, mut keep: impl FnMut(&mut T) -> bool){
+        for (i, s) in self.slots.iter_mut().enumerate() {
+            if s.value.as_mut().is_some_and(|v| !keep(v)) {
+                s.value = None;
+                s.gen = s.gen.wrapping_add(1);
+                self.free.push(i as u32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_handles_miss_the_reused_slot() {
+        let mut pool = Pool::new();
+        let a = pool.insert("a");
+        let b = pool.insert("b");
+        assert_eq!(pool.remove(a), Some("a"));
+        assert_eq!(pool.remove(a), None);
+        let c = pool.insert("c");
+        // same slot, new generation
+        assert_eq!(c.index, a.index);
+        assert_eq!((pool.get(a), pool.get(c), pool.get(b)), (None, Some(&"c"), Some(&"b")));
+        assert!(pool.get_mut(a).is_none());
+
+        pool.retain(|v| *v != "b");
+        assert_eq!(pool.values().collect::<Vec<_>>(), [&"c"]);
+        let d = pool.insert("d");
+        assert_eq!(d.index, b.index);
+        assert_eq!((pool.get(b), pool.get(d)), (None, Some(&"d")));
+        assert_eq!(pool.remove(b), None);
+        assert_eq!(pool.iter().map(|(h, v)| (h, *v)).collect::<Vec<_>>(), [(c, "c"), (d, "d")]);
+    }
+}
@@ -0,0 +1,136 @@
+// Named profiles. Each one is a folder under `<data dir>/profiles/` holding
+// its own save and replays, so several people can share a machine. The name
+// of the last profile played is remembered next to the folder.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::replay::REPLAY_DIR;
+use crate::save::{sign_unsigned, SAVE_FILE};
+
+const PROFILES_DIR: &str = "profiles";
+const LAST_FILE: &str = "last_profile";
+pub const DEFAULT_NAME: &str = "Player";
+const IMPORTED_NAME: &str = "Imported";
+pub const MAX_NAME: usize = 20;
+
+pub fn dir(data: &Path, name: &str) -> PathBuf { data.join(PROFILES_DIR).join(name) }
+
+/// Profile names, sorted case-insensitively.
+pub fn list(data: &Path) -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    match fs::read_dir(data.join(PROFILES_DIR)) {
+        Ok(rd) => for e in rd {
+            let e = e?;
+            if e.file_type()?.is_dir() { if let Ok(n) = e.file_name().into_string() { names.push(n); } }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    names.sort_by_key(|n| n.to_lowercase());
+    Ok(names)
+}
+
+/// Names double as folder names, so keep them to characters every
+/// filesystem accepts.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() { return Err("name cannot be empty".into()); }
+    if name != name.trim() { return Err("name cannot start or end with a space".into()); }
+    if name.chars().count() > MAX_NAME { return Err(format!("name is longer than {} characters", MAX_NAME)); }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_')) {
+        return Err("use letters, digits, spaces, - or _".into());
+    }
+    Ok(())
+}
+
+pub fn create(data: &Path, name: &str) -> Result<(), String> {
+    check_name(name)?;
+    if taken(data, name, None)? { return Err(format!("{} already exists", name)); }
+    fs::create_dir_all(dir(data, name)).map_err(|e| e.to_string())
+}
+
+pub fn rename(data: &Path, from: &str, to: &str) -> Result<(), String> {
+    check_name(to)?;
+    if taken(data, to, Some(from))? { return Err(format!("{} already exists", to)); }
+    fs::rename(dir(data, from), dir(data, to)).map_err(|e| e.to_string())?;
+    if last(data).as_deref() == Some(from) { set_last(data, to); }
+    Ok(())
+}
+
+pub fn delete(data: &Path, name: &str) -> Result<(), String> {
+    fs::remove_dir_all(dir(data, name)).map_err(|e| e.to_string())
+}
+
+/// Case-insensitive, since that is how Windows and macOS compare folders.
+fn taken(data: &Path, name: &str, except: Option<&str>) -> Result<bool, String> {
+    let names = list(data).map_err(|e| e.to_string())?;
+    Ok(names.iter().any(|n| n.eq_ignore_ascii_case(name) && Some(n.as_str()) != except))
+}
+
+pub fn last(data: &Path) -> Option<String> {
+    fs::read_to_string(data.join(LAST_FILE)).ok().map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Best effort: forgetting the last profile only costs a keypress.
+pub fn set_last(data: &Path, name: &str) { let _ = fs::write(data.join(LAST_FILE), name); }
+
+/// Whether profiles have never been set up in `data`: the one time an
+/// unsigned save from an older build can be taken on trust.
+pub fn first_run(data: &Path) -> bool { !data.join(PROFILES_DIR).exists() && !data.join(LAST_FILE).exists() }
+
+/// Moves a loose save and replays in the data folder into a profile of their
+/// own: `Player` for progress from before profiles existed, or a fresh
+/// `Imported` profile for a save brought in from the working directory once
+/// there are profiles already. Returns the name it was given, if there was
+/// anything to move.
+pub fn adopt_loose_save(data: &Path) -> io::Result<Option<String>> {
+    if !data.join(SAVE_FILE).is_file() { return Ok(None); }
+    let first = first_run(data);
+    let name = if data.join(PROFILES_DIR).exists() { unused_name(data, IMPORTED_NAME)? } else { DEFAULT_NAME.to_string() };
+    // saves from before profiles may predate signing too; anything turning up
+    // later is loaded like any other unsigned save and marked as modified
+    if first { sign_unsigned(&data.join(SAVE_FILE))?; }
+    let to = dir(data, &name);
+    fs::create_dir_all(&to)?;
+    for e in fs::read_dir(data)? {
+        let e = e?;
+        let name = e.file_name();
+        // the save plus its backups and set-aside copies
+        let keep = name.to_str().is_some_and(|n| n.starts_with(SAVE_FILE) || n == REPLAY_DIR);
+        if keep { fs::rename(e.path(), to.join(&name))?; }
+    }
+    set_last(data, &name);
+    Ok(Some(name))
+}
+
+/// `base`, or `base 2`, `base 3`... whichever is free first.
+fn unused_name(data: &Path, base: &str) -> io::Result<String> {
+    let names = list(data)?;
+    let free = |n: &String| !names.iter().any(|t| t.eq_ignore_ascii_case(n));
+    Ok(std::iter::once(base.to_string()).chain((2..).map(|i| format!("{} {}", base, i))).find(free).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loose_save_gets_its_own_profile_once_profiles_exist() {
+        let data = std::env::temp_dir().join(format!("neon_rush_test_{}_adopt", std::process::id()));
+        let _ = fs::remove_dir_all(&data);
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join(SAVE_FILE), "currency=10\n").unwrap();
+        assert_eq!(adopt_loose_save(&data).unwrap().as_deref(), Some(DEFAULT_NAME));
+        assert!(!crate::save::load(&dir(&data, DEFAULT_NAME).join(SAVE_FILE)).unwrap().unwrap().data.modified);
+        for expect in ["Imported", "Imported 2"] {
+            fs::write(data.join(SAVE_FILE), "currency=20\n").unwrap();
+            assert_eq!(adopt_loose_save(&data).unwrap().as_deref(), Some(expect));
+            assert!(dir(&data, expect).join(SAVE_FILE).is_file());
+            assert_eq!(last(&data).as_deref(), Some(expect));
+            assert!(crate::save::load(&dir(&data, expect).join(SAVE_FILE)).unwrap().unwrap().data.modified);
+        }
+        assert!(!data.join(SAVE_FILE).exists());
+        assert_eq!(adopt_loose_save(&data).unwrap(), None);
+    }
+}
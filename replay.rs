@@ -0,0 +1,135 @@
+// A run is fully determined by its seed, the starting upgrades and the input
+// fed to every tick, so that is all a replay stores. Inputs are
+// packed into one byte per tick and run-length encoded.
+
+use macroquad::math::vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::adaptive::Adaptive;
+use crate::enemies::registry;
+use crate::input::{InputSource, InputState, Scripted};
+use crate::sim::{Upgrades, World};
+use crate::waves::waves;
+
+pub const REPLAY_DIR: &str = "replays";
+const VERSION: u32 = 7;
+
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const UP: u8 = 4;
+const DOWN: u8 = 8;
+const DASH: u8 = 16;
+
+/// Something outside the input stream that changed the simulation mid-run.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Change { Upgrades(Upgrades) }
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    /// Fingerprint of the enemy table the run was played with.
+    pub enemies: u64,
+    /// Same for the wave script.
+    pub waves: u64,
+    pub upgrades: Upgrades,
+    /// Starting level of adaptive difficulty, if the run played with it.
+    #[serde(default)]
+    pub adaptive: Option<f32>,
+    pub score: i32,
+    pub ticks: u32,
+    pub inputs: Vec<(u8, u32)>,
+    pub changes: Vec<(u32, Change)>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, upgrades: Upgrades, adaptive: Option<f32>) -> Self {
+        Self { version: VERSION, seed, enemies: registry().fingerprint, waves: waves().fingerprint, upgrades, adaptive, score: 0, ticks: 0, inputs: vec![], changes: vec![] }
+    }
+
+    pub fn push(&mut self, input: &InputState){
+        let b = pack(input);
+        match self.inputs.last_mut() {
+            Some((last, n)) if *last == b => *n += 1,
+            _ => self.inputs.push((b, 1)),
+        }
+        self.ticks += 1;
+    }
+
+    /// Records a change that takes effect before the next pushed tick.
+    pub fn change(&mut self, c: Change){ self.changes.push((self.ticks, c)); }
+
+    pub fn world(&self) -> World {
+        let mut w = World::new(self.upgrades.clone(), self.seed);
+        w.adaptive = self.adaptive.map(Adaptive::new);
+        w
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let s = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let r: Replay = serde_json::from_str(&s).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if r.version != VERSION { return Err(format!("{}: unsupported replay version {}", path.display(), r.version)); }
+        if r.enemies != registry().fingerprint { return Err(format!("{}: recorded with a different enemy table", path.display())); }
+        if r.waves != waves().fingerprint { return Err(format!("{}: recorded with a different wave script", path.display())); }
+        Ok(r)
+    }
+}
+
+/// Feeds a recorded run back into a `World`, one tick at a time.
+pub struct Playback { input: Scripted, changes: VecDeque<(u32, Change)>, tick: u32 }
+
+impl Playback {
+    pub fn new(r: &Replay) -> Self {
+        let states = r.inputs.iter().flat_map(|(b, n)| std::iter::repeat_n(unpack(*b), *n as usize)).collect();
+        Self { input: Scripted::new(states), changes: r.changes.iter().cloned().collect(), tick: 0 }
+    }
+
+    pub fn next(&mut self, world: &mut World) -> InputState {
+        while self.changes.front().is_some_and(|(t, _)| *t <= self.tick) {
+            if let Some((_, Change::Upgrades(u))) = self.changes.pop_front() { world.upgrades = u; }
+        }
+        self.tick += 1;
+        self.input.poll()
+    }
+}
+
+/// Where the best-scoring run on `seed` is kept.
+pub fn best_path(dir: &Path, seed: u64) -> PathBuf { dir.join(REPLAY_DIR).join(format!("best-{}.json", seed)) }
+
+/// A recorded run simulated alongside the live one, tick for tick.
+pub struct Ghost { pub world: World, pub final_score: i32, playback: Playback }
+
+impl Ghost {
+    pub fn new(r: &Replay) -> Self { Self { world: r.world(), final_score: r.score, playback: Playback::new(r) } }
+
+    pub fn step(&mut self, dt: f32){
+        if self.world.over { return; }
+        let input = self.playback.next(&mut self.world);
+        self.world.update(dt, &input);
+        self.world.drain_events().for_each(drop);
+        self.world.take_credits();
+    }
+}
+
+fn pack(i: &InputState) -> u8 {
+    let mut b = 0;
+    if i.mv.x < -0.38 { b |= LEFT; }
+    if i.mv.x > 0.38 { b |= RIGHT; }
+    if i.mv.y < -0.38 { b |= UP; }
+    if i.mv.y > 0.38 { b |= DOWN; }
+    if i.dash { b |= DASH; }
+    b
+}
+
+fn unpack(b: u8) -> InputState {
+    let axis = |neg: u8, pos: u8| (b & pos != 0) as i32 as f32 - (b & neg != 0) as i32 as f32;
+    InputState { mv: vec2(axis(LEFT, RIGHT), axis(UP, DOWN)), dash: b & DASH != 0, ..Default::default() }
+}
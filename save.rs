@@ -0,0 +1,569 @@
+// Profile persistence. Saves are JSON with a schema version; older files are
+// migrated forward on load, starting from the original `key=value` format
+// (treated as version 0). Nothing is silently zeroed: unparseable values fail
+// the load, and unknown or missing fields come back as warnings.
+//
+// Writes go to a temp file that is renamed over the save, so a crash never
+// leaves a half-written file, and the previous saves are kept as `.bak1`..
+// `.bakN` for `load_or_recover` to fall back on.
+//
+// Every save carries a MAC over its contents. A save that fails the check,
+// or has none, still loads, but is marked `modified` for good so its scores
+// can be kept off the leaderboards.
+//
+// A profile can also travel as a text code, `NR1-<payload>-<check>`: the
+// signed save in URL-safe base64, plus a checksum that catches paste errors.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::sim::Upgrades;
+use crate::stats::Stats;
+
+pub const SAVE_FILE: &str = "neon_rush.sav";
+const SCHEMA_VERSION: u64 = 4;
+const MAC_KEY: &[u8] = b"neon rush save mac v2";
+const CODE_PREFIX: &str = "NR1";
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BACKUPS: usize = 3;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    pub currency: i32,
+    pub upgrades: Upgrades,
+    /// The profile's own best score. Kept alongside the leaderboard, which
+    /// only holds the machine's top runs across all profiles, never holds a
+    /// modified profile's runs, and cannot see runs from before it existed.
+    pub best: i32,
+    /// Set once a save fails its MAC check, and never cleared.
+    pub modified: bool,
+    pub stats: Stats,
+    /// Runs play with adaptive difficulty.
+    pub adaptive: bool,
+    /// The level adaptive difficulty starts the next run at.
+    pub difficulty: f32,
+}
+
+/// `unsigned` is set for formats from before saves carried a MAC.
+pub struct Loaded { pub data: SaveData, pub warnings: Vec<String>, pub migrated: bool, pub unsigned: bool }
+
+#[derive(Debug)]
+pub enum LoadError { Io(io::Error), Corrupt(String), UnsupportedVersion(u64) }
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "could not read save: {}", e),
+            LoadError::Corrupt(why) => write!(f, "save is corrupt: {}", why),
+            LoadError::UnsupportedVersion(v) => write!(f, "save is from a newer version of the game (schema {})", v),
+        }
+    }
+}
+
+/// `Ok(None)` means there is no save yet.
+pub fn load(path: &Path) -> Result<Option<Loaded>, LoadError> {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(LoadError::Io(e)),
+    };
+    parse(&text).map(Some)
+}
+
+/// Reads a save of any version. Formats from before saves were signed cannot
+/// be told apart from edited ones, so they come back marked as modified; the
+/// one-time moves of such saves go through `sign_unsigned` instead.
+pub fn parse(text: &str) -> Result<Loaded, LoadError> { parse_as(text, false) }
+
+fn parse_as(text: &str, trust_unsigned: bool) -> Result<Loaded, LoadError> {
+    let mut warnings = vec![];
+    let (mut value, from) = if text.trim_start().starts_with('{') {
+        let mut v: Value = serde_json::from_str(text).map_err(|e| LoadError::Corrupt(e.to_string()))?;
+        let ver = v.get("version").and_then(Value::as_u64).ok_or_else(|| LoadError::Corrupt("missing schema version".into()))?;
+        if (2..=SCHEMA_VERSION).contains(&ver) && !verify(&mut v) {
+            if let Value::Object(m) = &mut v { m.insert("modified".into(), true.into()); }
+            warnings.push("edited outside the game; this profile is now marked as modified".into());
+        }
+        (v, ver)
+    } else {
+        (parse_legacy(text, &mut warnings)?, 0)
+    };
+    if from > SCHEMA_VERSION { return Err(LoadError::UnsupportedVersion(from)); }
+    for v in from..SCHEMA_VERSION { value = migrate(value, v); }
+    let unsigned = from < 2;
+    if unsigned && !trust_unsigned {
+        if let Value::Object(m) = &mut value { m.insert("modified".into(), true.into()); }
+        warnings.push("saved without a signature; this profile is now marked as modified".into());
+    }
+
+    if let Value::Object(m) = &mut value { m.remove("version"); }
+    let data: SaveData = serde_json::from_value(value.clone()).map_err(|e| LoadError::Corrupt(e.to_string()))?;
+    let known = serde_json::to_value(&data).map_err(|e| LoadError::Corrupt(e.to_string()))?;
+    diff_fields(&value, &known, "", &mut warnings);
+    Ok(Loaded { data, warnings, migrated: from < SCHEMA_VERSION, unsigned })
+}
+
+/// Signs a save at `path` written before saves were signed, taking its
+/// contents on trust. Only for moving such a save out of the working
+/// directory or into its first profile, the two places they can come from.
+/// Anything that does not parse is left alone for the normal load to report.
+pub fn sign_unsigned(path: &Path) -> io::Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    match parse_as(&text, true) {
+        // `save` keeps the unsigned original as the first backup
+        Ok(l) if l.unsigned => save(path, &l.data),
+        _ => Ok(()),
+    }
+}
+
+/// The outcome of `load_or_recover`: what to play with, and what the player
+/// should be told about how we got it. `locked` means a save is there but
+/// could not be read for a reason other than damage, so nothing may be
+/// written over it.
+pub struct Recovered { pub data: Option<SaveData>, pub notices: Vec<String>, pub rewrite: bool, pub locked: bool }
+
+/// Loads the save, falling back to the newest backup that still loads if the
+/// primary file is corrupt. A corrupt primary is set aside so the next save
+/// cannot overwrite the evidence. A save from a newer build or one that could
+/// not be read is left where it is and the profile is locked instead, since
+/// a backup would roll its progress back.
+pub fn load_or_recover(path: &Path) -> Recovered {
+    let name = path.file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+    let err = match load(path) {
+        Ok(Some(l)) => return Recovered { notices: l.warnings.iter().map(|w| format!("{}: {}", name, w)).collect(), rewrite: l.migrated, data: Some(l.data), locked: false },
+        Ok(None) => return Recovered { data: None, notices: vec![], rewrite: false, locked: false },
+        Err(e) => e,
+    };
+    let mut notices = vec![format!("{}: {}", name, err)];
+    if !matches!(err, LoadError::Corrupt(_)) {
+        notices.push("progress will not be saved to this profile this session".into());
+        return Recovered { data: None, notices, rewrite: false, locked: true };
+    }
+    if let Err(e) = fs::rename(path, unused_sibling(path, "corrupt")) { notices.push(format!("could not move bad save aside: {}", e)); }
+    for i in 1..=BACKUPS {
+        if let Ok(Some(l)) = load(&backup(path, i)) {
+            notices.push(format!("restored progress from backup {}", i));
+            return Recovered { data: Some(l.data), notices, rewrite: true, locked: false };
+        }
+    }
+    notices.push("no usable backup found, starting a fresh profile".into());
+    Recovered { data: None, notices, rewrite: false, locked: false }
+}
+
+pub fn save(path: &Path, data: &SaveData) -> io::Result<()> {
+    let text = serde_json::to_string_pretty(&signed(data)?)?;
+    if path.exists() { rotate_backups(path)?; }
+    write_atomic(path, text.as_bytes())
+}
+
+/// Writes through a temp file renamed into place, so readers only ever see
+/// the old contents or the new ones.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+    let tmp = sibling(path, "tmp");
+    {
+        let mut f = fs::File::create(&tmp)?;
+        f.write_all(bytes)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+/// The save as written to disk: versioned and signed.
+fn signed(data: &SaveData) -> serde_json::Result<Value> {
+    let mut v = serde_json::to_value(data)?;
+    if let Value::Object(m) = &mut v {
+        m.insert("version".into(), SCHEMA_VERSION.into());
+        let mac = mac(&Value::Object(m.clone()));
+        m.insert("mac".into(), mac.into());
+    }
+    Ok(v)
+}
+
+pub fn export_code(data: &SaveData) -> String {
+    let payload = base64(signed(data).map(|v| v.to_string()).unwrap_or_default().as_bytes());
+    format!("{}-{}-{:08x}", CODE_PREFIX, payload, fnv(payload.as_bytes()) as u32)
+}
+
+/// Reads a code made by `export_code`, on this version or an older one. The
+/// payload goes through the same checks and migrations as a save file.
+pub fn import_code(code: &str) -> Result<Loaded, String> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    // base64 payloads may contain '-', so the checksum is whatever follows the last one
+    let Some((payload, check)) = code.strip_prefix(CODE_PREFIX).and_then(|c| c.strip_prefix('-')).and_then(|c| c.rsplit_once('-')) else {
+        return Err("not a profile code".into());
+    };
+    if format!("{:08x}", fnv(payload.as_bytes()) as u32) != check { return Err("code is damaged; copy it again".into()); }
+    let text = unbase64(payload).and_then(|b| String::from_utf8(b).ok()).ok_or("code is damaged; copy it again")?;
+    parse(&text).map_err(|e| e.to_string())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 4 / 3 + 3);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() { out.push(B64[(n >> (18 - 6 * i) & 63) as usize] as char); }
+    }
+    out
+}
+
+fn unbase64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        acc = acc << 6 | B64.iter().position(|b| *b == c)? as u32;
+        bits += 6;
+        if bits >= 8 { bits -= 8; out.push((acc >> bits) as u8); }
+    }
+    Some(out)
+}
+
+/// Keeps a copy of the current save, migrated or not, next to it without
+/// replacing an earlier copy.
+pub fn keep_copy(path: &Path, tag: &str) -> io::Result<()> { fs::copy(path, unused_sibling(path, tag)).map(drop) }
+
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    for i in (1..BACKUPS).rev() {
+        let from = backup(path, i);
+        if from.exists() { fs::rename(&from, backup(path, i + 1))?; }
+    }
+    fs::copy(path, backup(path, 1)).map(drop)
+}
+
+fn backup(path: &Path, i: usize) -> PathBuf { sibling(path, &format!("bak{}", i)) }
+
+/// `sibling(path, tag)`, or `tag2`, `tag3`... whichever is free first, so
+/// files set aside earlier are never overwritten.
+fn unused_sibling(path: &Path, tag: &str) -> PathBuf {
+    std::iter::once(sibling(path, tag)).chain((2..).map(|i| sibling(path, &format!("{}{}", tag, i))))
+        .find(|p| !p.exists()).unwrap_or_else(|| sibling(path, tag))
+}
+
+/// `neon_rush.sav` + `tmp` -> `neon_rush.sav.tmp`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+/// Keyed FNV-1a over the compact JSON, which serde_json writes with sorted
+/// keys. The key ships in the binary, so this catches a save (or the
+/// leaderboard) touched in a text editor rather than a determined forger.
+pub fn mac(v: &Value) -> String {
+    let bytes: Vec<u8> = MAC_KEY.iter().chain(v.to_string().as_bytes()).chain(MAC_KEY).copied().collect();
+    format!("{:016x}", fnv(&bytes))
+}
+
+pub fn fnv(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Strips the `mac` field and checks it against the rest of the save.
+pub fn verify(v: &mut Value) -> bool {
+    let Value::Object(m) = v else { return false };
+    let Some(Value::String(found)) = m.remove("mac") else { return false };
+    found == mac(v)
+}
+
+/// Upgrades a save one schema version, from `from` to `from + 1`.
+fn migrate(v: Value, from: u64) -> Value {
+    match from {
+        // version 0 is the flat key=value file
+        0 => {
+            let mut m = v.as_object().cloned().unwrap_or_default();
+            let mut up = Map::new();
+            for k in ["speed", "dash_cd", "dash_time", "shard_value", "magnet"] {
+                if let Some(val) = m.remove(k) { up.insert(k.into(), val); }
+            }
+            m.insert("upgrades".into(), Value::Object(up));
+            Value::Object(m)
+        }
+        1 => {
+            let mut m = v.as_object().cloned().unwrap_or_default();
+            m.insert("modified".into(), false.into());
+            Value::Object(m)
+        }
+        2 => {
+            let mut m = v.as_object().cloned().unwrap_or_default();
+            m.insert("stats".into(), serde_json::to_value(Stats::default()).unwrap_or_default());
+            Value::Object(m)
+        }
+        3 => {
+            let mut m = v.as_object().cloned().unwrap_or_default();
+            m.insert("adaptive".into(), false.into());
+            m.insert("difficulty".into(), 0.0.into());
+            Value::Object(m)
+        }
+        _ => v,
+    }
+}
+
+fn parse_legacy(text: &str, warnings: &mut Vec<String>) -> Result<Value, LoadError> {
+    let mut m = Map::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() { continue; }
+        let Some((k, v)) = line.split_once('=') else {
+            return Err(LoadError::Corrupt(format!("line {}: expected key=value, got {:?}", n + 1, line)));
+        };
+        let (k, v) = (k.trim(), v.trim());
+        let n: i64 = v.parse().map_err(|_| LoadError::Corrupt(format!("{}: {:?} is not a number", k, v)))?;
+        if m.insert(k.to_string(), Value::from(n)).is_some() { warnings.push(format!("{} appears more than once; using the last value", k)); }
+    }
+    Ok(Value::Object(m))
+}
+
+/// Reports keys present in the file but not understood, and keys we expected
+/// but did not find (those fall back to their defaults).
+fn diff_fields(found: &Value, known: &Value, prefix: &str, warnings: &mut Vec<String>) {
+    let (Value::Object(f), Value::Object(k)) = (found, known) else { return };
+    for (key, val) in f {
+        match k.get(key) {
+            Some(kv) => diff_fields(val, kv, &format!("{}{}.", prefix, key), warnings),
+            None => warnings.push(format!("unknown field {}{} ignored", prefix, key)),
+        }
+    }
+    for key in k.keys().filter(|key| !f.contains_key(*key)) {
+        warnings.push(format!("missing field {}{} reset to default", prefix, key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::TICK;
+
+    /// A fresh, empty folder under the system temp dir.
+    fn temp_dir(name: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("neon_rush_test_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&d);
+        fs::create_dir_all(&d).unwrap();
+        d
+    }
+
+    fn sample() -> SaveData {
+        let mut d = SaveData { currency: 1234, best: 987, adaptive: true, difficulty: 0.123_456_7, ..Default::default() };
+        d.upgrades.speed = 3;
+        d.upgrades.magnet = 1;
+        d.stats.runs = 17;
+        d.stats.play_time = 3.0 * TICK as f64;
+        d.stats.credits_earned = 4321;
+        d.stats.deaths.bullet = 2;
+        d
+    }
+
+    fn same(a: &SaveData, b: &SaveData) -> bool { serde_json::to_value(a).unwrap() == serde_json::to_value(b).unwrap() }
+
+    fn written(data: &SaveData) -> String { serde_json::to_string_pretty(&signed(data).unwrap()).unwrap() }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let path = temp_dir("round_trip").join(SAVE_FILE);
+        let data = sample();
+        save(&path, &data).unwrap();
+        let l = load(&path).unwrap().unwrap();
+        assert!(l.warnings.is_empty(), "{:?}", l.warnings);
+        assert!(!l.data.modified && !l.migrated);
+        assert!(same(&l.data, &data));
+    }
+
+    #[test]
+    fn accumulated_play_time_keeps_its_signature() {
+        // play time is a long sum of ticks; every value must read back bit for bit
+        let mut data = sample();
+        for _ in 0..5000 {
+            data.stats.play_time += TICK as f64;
+            data.difficulty = (data.difficulty + 0.003_7) % 0.3;
+            let l = parse(&written(&data)).unwrap();
+            assert!(!l.data.modified, "play_time {} read back as edited", data.stats.play_time);
+            assert_eq!(l.data.stats.play_time.to_bits(), data.stats.play_time.to_bits());
+        }
+    }
+
+    #[test]
+    fn edited_save_is_marked_modified_for_good() {
+        let text = written(&sample()).replace("\"currency\": 1234", "\"currency\": 999999");
+        let l = parse(&text).unwrap();
+        assert!(l.data.modified);
+        assert_eq!(l.data.currency, 999_999);
+        assert!(l.warnings.iter().any(|w| w.contains("edited outside the game")));
+        // signing it again does not clear the flag
+        let again = parse(&written(&l.data)).unwrap();
+        assert!(again.data.modified && again.warnings.is_empty());
+    }
+
+    #[test]
+    fn unsigned_formats_are_marked_modified() {
+        for text in ["currency=999999\nbest=100000\n", r#"{"version": 1, "currency": 999999, "best": 100000, "upgrades": {}}"#] {
+            let l = parse(text).unwrap();
+            assert!(l.unsigned && l.data.modified, "{}", text);
+            assert_eq!(l.data.currency, 999_999);
+        }
+        let payload = base64(br#"{"version": 1, "currency": 999999}"#);
+        let code = format!("{}-{}-{:08x}", CODE_PREFIX, payload, fnv(payload.as_bytes()) as u32);
+        assert!(import_code(&code).unwrap().data.modified);
+    }
+
+    #[test]
+    fn sign_unsigned_trusts_a_legacy_save_once() {
+        let path = temp_dir("sign_unsigned").join(SAVE_FILE);
+        fs::write(&path, "currency=250\nbest=40\nspeed=2\n").unwrap();
+        sign_unsigned(&path).unwrap();
+        let l = load(&path).unwrap().unwrap();
+        assert!(!l.data.modified && !l.unsigned && l.warnings.is_empty(), "{:?}", l.warnings);
+        assert_eq!((l.data.currency, l.data.best, l.data.upgrades.speed), (250, 40, 2));
+        // the original is kept, and still counts as unsigned anywhere else
+        assert!(parse(&fs::read_to_string(backup(&path, 1)).unwrap()).unwrap().data.modified);
+    }
+
+    /// Signs `v` as a save of whatever version it claims.
+    fn resign(mut v: Value) -> String {
+        if let Value::Object(m) = &mut v {
+            m.remove("mac");
+            let mac = mac(&Value::Object(m.clone()));
+            m.insert("mac".into(), mac.into());
+        }
+        v.to_string()
+    }
+
+    #[test]
+    fn legacy_save_migrates_all_the_way() {
+        let l = parse("currency = 120\nbest=45\nspeed=2\ndash_cd=1\nmagnet=3\n\n").unwrap();
+        assert!(l.migrated && l.unsigned);
+        assert_eq!((l.data.currency, l.data.best), (120, 45));
+        assert_eq!((l.data.upgrades.speed, l.data.upgrades.dash_cd, l.data.upgrades.dash_time, l.data.upgrades.magnet), (2, 1, 0, 3));
+        assert_eq!(l.data.stats.runs, 0);
+        assert!(!l.data.adaptive);
+        // only the missing upgrade levels and the signature are worth mentioning
+        assert!(l.warnings.iter().all(|w| w.contains("upgrades.") || w.contains("signature")), "{:?}", l.warnings);
+    }
+
+    #[test]
+    fn legacy_save_problems() {
+        assert!(matches!(parse("currency=lots\n"), Err(LoadError::Corrupt(_))));
+        assert!(matches!(parse("currency 12\n"), Err(LoadError::Corrupt(_))));
+        let l = parse("currency=1\ncurrency=2\n").unwrap();
+        assert_eq!(l.data.currency, 2);
+        assert!(l.warnings.iter().any(|w| w.contains("more than once")));
+    }
+
+    #[test]
+    fn signed_v2_save_migrates_cleanly() {
+        let v = serde_json::json!({ "version": 2, "currency": 7, "best": 3, "modified": false,
+            "upgrades": { "speed": 1, "dash_cd": 0, "dash_time": 0, "shard_value": 0, "magnet": 0 } });
+        let l = parse(&resign(v)).unwrap();
+        assert!(l.migrated && !l.unsigned && !l.data.modified);
+        assert!(l.warnings.is_empty(), "{:?}", l.warnings);
+        assert_eq!((l.data.currency, l.data.upgrades.speed, l.data.difficulty), (7, 1, 0.0));
+    }
+
+    #[test]
+    fn unknown_and_missing_fields_are_reported() {
+        let mut v = signed(&sample()).unwrap();
+        let m = v.as_object_mut().unwrap();
+        m.insert("colour".into(), "pink".into());
+        m.remove("stats");
+        let l = parse(&resign(v)).unwrap();
+        assert!(!l.data.modified);
+        assert!(l.warnings.iter().any(|w| w == "unknown field colour ignored"), "{:?}", l.warnings);
+        assert!(l.warnings.iter().any(|w| w == "missing field stats reset to default"), "{:?}", l.warnings);
+    }
+
+    #[test]
+    fn newer_or_broken_saves_do_not_load() {
+        assert!(matches!(parse(r#"{"version": 99}"#), Err(LoadError::UnsupportedVersion(99))));
+        assert!(matches!(parse(r#"{"currency": 1}"#), Err(LoadError::Corrupt(_))));
+        assert!(matches!(parse(r#"{"version": 4, "#), Err(LoadError::Corrupt(_))));
+    }
+
+    #[test]
+    fn saves_rotate_through_the_backups() {
+        let path = temp_dir("rotate").join(SAVE_FILE);
+        for currency in 0..5 { save(&path, &SaveData { currency, ..Default::default() }).unwrap(); }
+        assert_eq!(load(&path).unwrap().unwrap().data.currency, 4);
+        for i in 1..=BACKUPS { assert_eq!(load(&backup(&path, i)).unwrap().unwrap().data.currency, 4 - i as i32); }
+        assert!(!backup(&path, BACKUPS + 1).exists());
+    }
+
+    #[test]
+    fn corrupt_save_falls_back_to_the_newest_good_backup() {
+        let path = temp_dir("recover").join(SAVE_FILE);
+        for currency in 0..3 { save(&path, &SaveData { currency, ..Default::default() }).unwrap(); }
+        fs::write(backup(&path, 1), "{").unwrap();
+        for round in 0..2 {
+            fs::write(&path, "{ not json").unwrap();
+            let r = load_or_recover(&path);
+            assert!(r.rewrite && !r.locked);
+            assert_eq!(r.data.unwrap().currency, 0);
+            assert!(r.notices.iter().any(|n| n.contains("backup 2")), "{:?}", r.notices);
+            assert!(!path.exists());
+            // a second bad save is set aside next to the first, not over it
+            let aside = if round == 0 { sibling(&path, "corrupt") } else { sibling(&path, "corrupt2") };
+            assert_eq!(fs::read_to_string(aside).unwrap(), "{ not json");
+        }
+    }
+
+    #[test]
+    fn newer_save_locks_the_profile_instead_of_rolling_back() {
+        let path = temp_dir("locked").join(SAVE_FILE);
+        save(&path, &sample()).unwrap();
+        save(&path, &sample()).unwrap();
+        fs::write(&path, r#"{"version": 99}"#).unwrap();
+        let r = load_or_recover(&path);
+        assert!(r.locked && !r.rewrite && r.data.is_none());
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"version": 99}"#);
+        assert!(!sibling(&path, "corrupt").exists());
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for n in 0..=bytes.len() {
+            let text = base64(&bytes[..n]);
+            assert!(text.bytes().all(|c| B64.contains(&c)));
+            assert_eq!(unbase64(&text).as_deref(), Some(&bytes[..n]));
+        }
+        assert_eq!(unbase64("ab+c"), None);
+        assert_eq!(unbase64("ab=="), None);
+    }
+
+    #[test]
+    fn export_code_round_trips() {
+        let data = sample();
+        let l = import_code(&format!("  {}\n", export_code(&data))).unwrap();
+        assert!(!l.data.modified && l.warnings.is_empty(), "{:?}", l.warnings);
+        assert!(same(&l.data, &data));
+    }
+
+    #[test]
+    fn code_with_dashes_in_the_payload_imports() {
+        // '-' encodes bytes ending in 0b111110 such as '>' and '~', which
+        // today's saves never contain; a future string field could
+        let mut v = signed(&sample()).unwrap();
+        v.as_object_mut().unwrap().insert("note".into(), "~>~>".into());
+        let payload = base64(resign(v).as_bytes());
+        assert!(payload.contains('-'));
+        let l = import_code(&format!("{}-{}-{:08x}", CODE_PREFIX, payload, fnv(payload.as_bytes()) as u32)).unwrap();
+        assert!(!l.data.modified && same(&l.data, &sample()));
+    }
+
+    #[test]
+    fn damaged_codes_are_refused() {
+        let code = export_code(&sample());
+        let mut bytes = code.clone().into_bytes();
+        let i = CODE_PREFIX.len() + 5;
+        bytes[i] = if bytes[i] == b'A' { b'B' } else { b'A' };
+        assert_eq!(import_code(&String::from_utf8(bytes).unwrap()).err().as_deref(), Some("code is damaged; copy it again"));
+        assert_eq!(import_code(&code[..code.len() - 1]).err().as_deref(), Some("code is damaged; copy it again"));
+        assert_eq!(import_code("hello").err().as_deref(), Some("not a profile code"));
+    }
+}
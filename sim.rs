@@ -0,0 +1,859 @@
+// Gameplay simulation. Nothing in here touches the window, the keyboard or the
+// wall clock: play happens in a fixed logical arena and an input snapshot and
+// the timestep are passed in, so a `World` can be stepped in tests, CI or
+// balance scripts with no display.
+
+use macroquad::math::{vec2, Vec2};
+use crate::adaptive::Adaptive;
+use crate::enemies::{registry, Movement, SpawnAt};
+use crate::grid::Grid;
+use crate::input::InputState;
+use crate::pool::{Handle, Pool};
+use crate::waves::{pick_weighted, waves, Director, Formation};
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use ::rand::{Rng, SeedableRng};
+// StdRng may change algorithm between rand releases; ChaCha8 is pinned, so
+// recorded seeds keep replaying the same run.
+use rand_chacha::ChaCha8Rng;
+
+/// The playfield in logical units, whatever the window size.
+pub const ARENA: Vec2 = Vec2::new(1280.0, 720.0);
+
+pub const PLAYER_SPEED: f32 = 360.0;
+pub const PLAYER_RADIUS: f32 = 15.0;
+pub const DASH_SPEED: f32 = 920.0;
+pub const DASH_TIME: f32 = 0.16;
+pub const DASH_COOLDOWN: f32 = 0.9;
+
+pub const SHARD_SPAWN_RATE: f32 = 1.1;
+pub const SHARD_RADIUS: f32 = 9.0;
+
+pub const NEAR_MISS_DIST: f32 = 36.0;
+pub const NEAR_MISS_BONUS: i32 = 3;
+
+pub const COMBO_TIME: f32 = 2.2;
+pub const COMBO_INC: f32 = 0.1;
+pub const TRAIL_MAX: usize = 42;
+
+const GRID_CELL: f32 = 64.0;
+/// Enemies that keep their distance also keep this far inside the walls.
+const KEEP_MARGIN: f32 = 40.0;
+/// Enemies that appear inside the arena try to stay this far from the player.
+const SAFE_SPAWN: f32 = 220.0;
+/// Largest bullet anyone may fire; the bullet grid is queried with this margin.
+pub const MAX_BULLET_RADIUS: f32 = 6.0;
+const POWERUP_RADIUS: f32 = 12.0;
+
+/// Length of one simulation step. The front end accumulates frame time and
+/// steps in whole ticks so play is identical at any refresh rate.
+pub const TICK: f32 = 1.0 / 120.0;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Upgrades {
+    pub speed: u32,
+    pub dash_cd: u32,
+    pub dash_time: u32,
+    pub shard_value: u32,
+    pub magnet: u32,
+}
+
+impl Upgrades {
+    pub fn player_speed(&self) -> f32 { PLAYER_SPEED * (1.0 + 0.06 * self.speed as f32) }
+    pub fn dash_cd(&self) -> f32 { DASH_COOLDOWN * libm::powf(0.88, self.dash_cd as f32) }
+    pub fn dash_time(&self) -> f32 { DASH_TIME * (1.0 + 0.08 * self.dash_time as f32) }
+    pub fn shard_currency_bonus(&self) -> i32 { (self.shard_value as i32) * 2 }
+    pub fn magnet_speed(&self) -> f32 { 120.0 + 50.0 * self.magnet as f32 }
+
+    pub fn cost_speed(&self) -> i32 { 60 + (self.speed as i32) * 45 }
+    pub fn cost_dash_cd(&self) -> i32 { 80 + (self.dash_cd as i32) * 50 }
+    pub fn cost_dash_time(&self) -> i32 { 80 + (self.dash_time as i32) * 50 }
+    pub fn cost_shard_value(&self) -> i32 { 40 + (self.shard_value as i32) * 30 }
+    pub fn cost_magnet(&self) -> i32 { 50 + (self.magnet as i32) * 40 }
+}
+
+/// Things that happened during a step that the front end may want to show.
+#[derive(Clone, Copy)]
+pub enum Event {
+    Dash(Vec2),
+    EnemyKilled(Vec2),
+    ShardCollected { pos: Vec2, bonus: i32 },
+    NearMiss(Vec2),
+    PowerUp { pos: Vec2, kind: PowerUpKind },
+    BossSpawned,
+    BossCleared(Vec2),
+    /// `number` counts from 1; `name` is the theme's announcement.
+    WaveStarted { number: u32, name: &'static str },
+    BulletBlocked(Vec2),
+    GameOver(Vec2),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeathCause { Enemy, Bullet, Boss }
+
+/// Where score and credits came from, for the end-of-run breakdown.
+#[derive(Clone, Copy)]
+pub enum Source { Shards, Kills, NearMiss, Boss }
+
+#[derive(Clone, Default, Serialize)]
+pub struct Breakdown { pub shards: i32, pub kills: i32, pub near_miss: i32, pub boss: i32 }
+
+impl Breakdown {
+    fn add(&mut self, src: Source, n: i32){
+        match src {
+            Source::Shards => self.shards += n,
+            Source::Kills => self.kills += n,
+            Source::NearMiss => self.near_miss += n,
+            Source::Boss => self.boss += n,
+        }
+    }
+}
+
+impl DeathCause {
+    pub fn name(&self) -> &'static str {
+        match self { DeathCause::Enemy => "enemy", DeathCause::Bullet => "bullet", DeathCause::Boss => "boss" }
+    }
+}
+
+fn clamp(v: f32, lo: f32, hi: f32) -> f32 { v.max(lo).min(hi) }
+
+pub struct Player {
+    pub pos: Vec2,
+    pub prev: Vec2,
+    pub vel: Vec2,
+    pub r: f32,
+    pub dash_cd: f32,
+    pub dash_t: f32,
+    pub invuln: f32,
+    pub dashes_left: i32,
+    pub dashes_max: i32,
+    pub trail: VecDeque<(Vec2, f32)>,
+}
+impl Player {
+    pub fn new(pos: Vec2) -> Self { Self { pos, prev: pos, vel: Vec2::ZERO, r: PLAYER_RADIUS, dash_cd: 0.0, dash_t: 0.0, invuln: 0.0, dashes_left: 1, dashes_max: 1, trail: VecDeque::with_capacity(TRAIL_MAX + 1) } }
+    pub fn is_dashing(&self) -> bool { self.dash_t > 0.0 }
+    fn update(&mut self, dt: f32, mv: Vec2, move_speed: f32, arena: Vec2) {
+        let mv = if mv.length_squared() > 0.0 { mv.normalize() } else { Vec2::ZERO };
+        self.prev = self.pos;
+
+        if self.is_dashing(){
+            self.dash_t -= dt;
+        } else {
+            self.vel = mv * move_speed;
+            if self.dash_cd>0.0 {
+                self.dash_cd -= dt;
+                if self.dash_cd <= 0.0 { self.dashes_left = self.dashes_max; self.dash_cd = 0.0; }
+            }
+        }
+        self.pos += self.vel * dt;
+        self.pos.x = clamp(self.pos.x, self.r, arena.x-self.r);
+        self.pos.y = clamp(self.pos.y, self.r, arena.y-self.r);
+        if self.invuln>0.0 { self.invuln -= dt; }
+
+        self.trail.push_back((self.pos, 0.35));
+        if self.trail.len()>TRAIL_MAX { self.trail.pop_front(); }
+        for p in &mut self.trail { p.1 -= dt; }
+        // every point ages at the same rate, so the expired ones are the oldest
+        while self.trail.front().is_some_and(|p| p.1 <= 0.0) { self.trail.pop_front(); }
+    }
+    fn try_dash(&mut self, dash_time: f32, dash_cd_total: f32) -> bool {
+        if self.dashes_left > 0 && !self.is_dashing(){
+            let dir = if self.vel.length_squared()==0.0 { vec2(1.0,0.0) } else { self.vel.normalize() };
+            self.vel = dir * DASH_SPEED;
+            self.dash_t = dash_time;
+            self.invuln = dash_time;
+            self.dashes_left -= 1;
+            if self.dashes_left == 0 { self.dash_cd = dash_cd_total; }
+            return true;
+        }
+        false
+    }
+}
+
+/// Where a charging enemy is in its approach, windup, lunge cycle.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stage { Approach, Windup, Lunge }
+
+/// `kind` indexes the archetype table in `enemies`. `stage`, `timer` and
+/// `dir` are only used by the behaviours that need them.
+pub struct Enemy {
+    pub pos: Vec2, pub prev: Vec2, pub vel: Vec2, pub kind: usize, pub r: f32, pub angle: f32, pub speed: f32, pub cool: f32,
+    pub stage: Stage, pub timer: f32, pub dir: Vec2,
+}
+impl Enemy {
+    /// `pace` scales the archetype's speed.
+    fn new(pos: Vec2, kind: usize, angle: f32, pace: f32) -> Self {
+        let a = registry().get(kind);
+        Self {
+            pos, prev: pos, vel: Vec2::ZERO, kind, r: a.radius, angle, speed: a.speed * pace, cool: a.fire.map_or(0.0, |f| f.cooldown[0]),
+            stage: Stage::Approach, timer: 0.0, dir: Vec2::ZERO,
+        }
+    }
+    fn update(&mut self, dt: f32, player: &Player, t: f32, arena: Vec2){
+        self.prev = self.pos;
+        let a = registry().get(self.kind);
+        let v = match a.movement {
+            Movement::Wander => {
+                let to_c = (arena*0.5 - self.pos) * 0.2;
+                let n = vec2(libm::cosf(t*1.7 + self.pos.x*0.01), libm::sinf(t*1.3 + self.pos.y*0.01));
+                let sum = to_c + n*120.0; if sum.length_squared()>0.0 { sum.normalize() * self.speed } else { Vec2::ZERO }
+            }
+            Movement::Chase => {
+                let mut v = player.pos - self.pos; if v.length_squared()>0.0 { v = v.normalize(); } v * self.speed
+            }
+            Movement::Orbit { radius, spin } => {
+                let offset = heading(self.angle) * radius;
+                let mut v = (player.pos + offset) - self.pos; if v.length_squared()>0.0 { v = v.normalize(); }
+                self.angle += spin*dt; v * self.speed
+            }
+            Movement::Charge { range, windup, lunge_speed, lunge_time, rest } => {
+                self.timer -= dt;
+                match self.stage {
+                    Stage::Approach => {
+                        let to = player.pos - self.pos;
+                        if self.timer <= 0.0 && to.length() < range {
+                            self.stage = Stage::Windup; self.timer = windup; self.dir = to.normalize_or_zero();
+                            Vec2::ZERO
+                        } else { to.normalize_or_zero() * self.speed }
+                    }
+                    Stage::Windup => {
+                        if self.timer <= 0.0 { self.stage = Stage::Lunge; self.timer = lunge_time; }
+                        Vec2::ZERO
+                    }
+                    Stage::Lunge => {
+                        if self.timer <= 0.0 { self.stage = Stage::Approach; self.timer = rest; }
+                        self.dir * lunge_speed
+                    }
+                }
+            }
+            Movement::Keep { distance } => {
+                let away = self.pos - player.pos;
+                let out = away.normalize_or_zero();
+                let radial = out * ((distance - away.length()) / distance).clamp(-1.0, 1.0);
+                // drift around the player, changing direction now and then
+                self.angle += dt*0.4;
+                let side = vec2(-out.y, out.x) * 0.5 * libm::sinf(self.angle).signum();
+                let inside = self.pos.clamp(Vec2::splat(KEEP_MARGIN), arena - KEEP_MARGIN) - self.pos;
+                (radial + side + inside.normalize_or_zero()).clamp_length_max(1.0) * self.speed
+            }
+            Movement::Anchor { period, grow } => {
+                let pulse = 0.5 + 0.5*libm::sinf(t*std::f32::consts::TAU/period + self.angle);
+                self.r = a.radius * (1.0 + grow*pulse);
+                Vec2::ZERO
+            }
+            // already steered by `World::steer_flocks`
+            Movement::Flock { .. } => self.vel,
+        };
+        self.vel = v;
+        self.pos += v * dt;
+    }
+}
+
+pub struct Shard { pub pos: Vec2, pub prev: Vec2, pub r: f32, pub t: f32 }
+impl Shard {
+    fn new(pos: Vec2, t: f32) -> Self { Self { pos, prev: pos, r: SHARD_RADIUS, t } }
+}
+
+/// Unit vector at angle `a`. Everything transcendental in the sim goes
+/// through libm rather than the platform's maths library, whose last bits
+/// differ between systems and would desync replays shared across them.
+pub fn heading(a: f32) -> Vec2 { vec2(libm::cosf(a), libm::sinf(a)) }
+
+fn rand_angle(rng: &mut ChaCha8Rng) -> f32 { rng.gen::<f32>() * std::f32::consts::TAU }
+
+pub struct Bullet { pub pos: Vec2, pub prev: Vec2, pub vel: Vec2, pub r: f32, pub hostile: bool, pub life: f32 }
+impl Bullet {
+    fn hostile(pos: Vec2, vel: Vec2, r: f32, life: f32) -> Self { Self { pos, prev: pos, vel, r, hostile: true, life } }
+}
+#[derive(Copy, Clone)]
+pub enum PowerUpKind { Invuln, Magnet, DoubleDash }
+pub struct PowerUp { pub pos: Vec2, pub kind: PowerUpKind, pub r: f32 }
+/// A spawn on its way in: `pos` is where it will appear, just outside an
+/// edge or on an in-arena portal.
+pub struct Telegraph { pub pos: Vec2, pub kind: usize, pub timer: f32 }
+impl Telegraph {
+    /// 0 when announced, 1 as the enemy appears.
+    pub fn progress(&self) -> f32 {
+        let lead = waves().warning;
+        if lead > 0.0 { 1.0 - (self.timer / lead).clamp(0.0, 1.0) } else { 1.0 }
+    }
+}
+pub struct Boss { pub pos: Vec2, pub prev: Vec2, pub r: f32, pub timer: f32, pub phase: f32, pub volley_t: f32, pub aim_t: f32 }
+
+pub struct World {
+    pub seed: u64,
+    pub arena: Vec2,
+    pub time: f32,
+    pub upgrades: Upgrades,
+    pub player: Player,
+    pub enemies: Pool<Enemy>,
+    pub shards: Pool<Shard>,
+    pub bullets: Pool<Bullet>,
+    pub powerups: Pool<PowerUp>,
+    pub boss: Option<Boss>,
+    pub director: Director,
+    /// Spawns announced but not yet in play.
+    pub telegraphs: Vec<Telegraph>,
+    /// Set when the run plays with adaptive difficulty.
+    pub adaptive: Option<Adaptive>,
+    pub shard_timer: f32,
+    pub powerup_timer: f32,
+    pub score: i32,
+    pub combo: f32,
+    pub combo_t: f32,
+    pub combo_peak: f32,
+    pub credits: i32,
+    pub score_by: Breakdown,
+    pub credits_by: Breakdown,
+    /// The part of shard score that came from the combo multiplier.
+    pub combo_bonus: i32,
+    pub bosses_cleared: u32,
+    pub power_invuln: f32,
+    pub power_magnet: f32,
+    pub power_ddash: f32,
+    pub next_boss_score: i32,
+    pub over: bool,
+    pub death: Option<(DeathCause, Vec2)>,
+    unbanked: i32,
+    events: Vec<Event>,
+    rng: ChaCha8Rng,
+    enemy_grid: Grid,
+    shard_grid: Grid,
+    powerup_grid: Grid,
+    bullet_grid: Grid,
+    near: Vec<Handle>,
+    steer: Vec<(Handle, Vec2)>,
+}
+
+impl World {
+    /// Every random decision in a run comes from `seed`, so the same seed and
+    /// the same inputs always play out the same way.
+    pub fn new(upgrades: Upgrades, seed: u64) -> Self {
+        Self {
+            seed,
+            arena: ARENA,
+            time: 0.0,
+            upgrades,
+            player: Player::new(ARENA*0.5),
+            enemies: Pool::new(),
+            shards: Pool::new(),
+            bullets: Pool::new(),
+            powerups: Pool::new(),
+            boss: None,
+            director: Director::default(),
+            telegraphs: vec![],
+            adaptive: None,
+            shard_timer: SHARD_SPAWN_RATE,
+            powerup_timer: 6.0,
+            score: 0,
+            combo: 0.0,
+            combo_t: 0.0,
+            combo_peak: 0.0,
+            score_by: Breakdown::default(),
+            credits_by: Breakdown::default(),
+            combo_bonus: 0,
+            bosses_cleared: 0,
+            credits: 0,
+            power_invuln: 0.0,
+            power_magnet: 0.0,
+            power_ddash: 0.0,
+            next_boss_score: 200,
+            over: false,
+            death: None,
+            unbanked: 0,
+            events: vec![],
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            enemy_grid: Grid::new(GRID_CELL),
+            shard_grid: Grid::new(GRID_CELL),
+            powerup_grid: Grid::new(GRID_CELL),
+            bullet_grid: Grid::new(GRID_CELL),
+            near: vec![],
+            steer: vec![],
+        }
+    }
+
+    /// Events raised since the last call, oldest first.
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, Event> { self.events.drain(..) }
+
+    /// Credits earned since the last call; `credits` keeps the run total.
+    pub fn take_credits(&mut self) -> i32 { std::mem::take(&mut self.unbanked) }
+
+    pub fn update(&mut self, dt: f32, input: &InputState){
+        if self.over { return; }
+        self.time += dt;
+
+        if self.power_invuln > 0.0 { self.power_invuln -= dt; }
+        if self.power_magnet > 0.0 { self.power_magnet -= dt; }
+        if self.power_ddash > 0.0 { self.power_ddash -= dt; }
+        self.player.dashes_max = if self.power_ddash > 0.0 { 2 } else { 1 };
+
+        if input.dash && self.player.try_dash(self.upgrades.dash_time(), self.upgrades.dash_cd()) {
+            self.events.push(Event::Dash(self.player.pos));
+        }
+        self.player.update(dt, input.mv, self.upgrades.player_speed(), self.arena);
+
+        if let Some(a) = &mut self.adaptive { a.update(dt, self.combo, self.time); }
+        self.direct_waves(dt);
+        self.materialise(dt);
+
+        if self.boss.is_none() && self.score >= self.next_boss_score {
+            let pos = vec2(self.arena.x*0.5, self.arena.y*0.35);
+            let b = Boss{ pos, prev: pos, r: 46.0, timer: 20.0, phase: 0.0, volley_t: 0.0, aim_t: 0.0 };
+            self.events.push(Event::BossSpawned);
+            self.boss = Some(b);
+        }
+        if let Some(mut b) = self.boss.take() {
+            b.prev = b.pos;
+            b.phase += dt; b.timer -= dt;
+            b.pos.x = self.arena.x*0.5 + libm::sinf(b.phase*1.2)* (self.arena.x*0.35);
+
+            b.volley_t -= dt;
+            if b.volley_t <= 0.0 {
+                b.volley_t += 1.6;
+                for i in 0..16 {
+                    let ang = i as f32 / 16.0 * std::f32::consts::TAU;
+                    let v = heading(ang) * 240.0;
+                    self.bullets.insert(Bullet::hostile(b.pos, v, 6.0, 6.0));
+                }
+            }
+
+            b.aim_t -= dt;
+            if b.aim_t <= 0.0 {
+                b.aim_t += 0.6;
+                let mut dir = self.player.pos - b.pos; if dir.length_squared()>0.0 { dir = dir.normalize(); }
+                self.bullets.insert(Bullet::hostile(b.pos, dir * 420.0, 5.0, 5.0));
+            }
+
+            let player_hit_boss = self.player.pos.distance(b.pos) <= self.player.r + b.r
+                && !(self.player.invuln>0.0 || self.power_invuln>0.0);
+            if player_hit_boss { self.game_over(DeathCause::Boss); }
+
+            if b.timer <= 0.0 {
+                self.events.push(Event::BossCleared(b.pos));
+                self.bosses_cleared += 1;
+                self.earn(Source::Boss, 50); self.bump_score(Source::Boss, 50);
+                self.next_boss_score += 250; // do not restore boss -> it despawns
+            } else {
+                self.boss = Some(b);
+            }
+        }
+
+        self.shard_timer -= dt;
+        if self.shard_timer <= 0.0 { self.spawn_shard(); self.shard_timer = SHARD_SPAWN_RATE; }
+
+        self.powerup_timer -= dt;
+        if self.powerup_timer <= 0.0 {
+            let k = if self.rng.gen::<f32>() < 0.34 { PowerUpKind::Invuln } else if self.rng.gen::<f32>() < 0.5 { PowerUpKind::Magnet } else { PowerUpKind::DoubleDash };
+            let pos = vec2(self.rng.gen_range(40.0..(self.arena.x-40.0)), self.rng.gen_range(40.0..(self.arena.y-40.0)));
+            self.powerups.insert(PowerUp{ pos, kind: k, r: POWERUP_RADIUS });
+            self.powerup_timer = self.rng.gen_range(7.0..13.0);
+        }
+
+        self.steer_flocks(dt);
+        for e in self.enemies.values_mut() { e.update(dt, &self.player, self.time, self.arena); }
+
+        let fire_scale = self.adapt(Adaptive::fire_scale);
+        for e in self.enemies.values_mut() {
+            let Some(fire) = registry().get(e.kind).fire else { continue };
+            e.cool -= dt;
+            if e.cool <= 0.0 {
+                for vel in fire.volley(e.pos, self.player.pos) {
+                    self.bullets.insert(Bullet::hostile(e.pos, vel, fire.radius, fire.life));
+                }
+                e.cool = self.rng.gen_range(fire.cooldown[0]..=fire.cooldown[1]) / fire_scale;
+            }
+        }
+
+        let pr = self.player.r; let ppos = self.player.pos;
+
+        self.enemy_grid.clear(self.arena);
+        for (h, e) in self.enemies.iter() { self.enemy_grid.insert(h, e.pos); }
+
+        if self.player.invuln<=0.0 {
+            self.enemy_grid.query(ppos, NEAR_MISS_DIST, &mut self.near);
+            for k in 0..self.near.len() {
+                let Some(e) = self.enemies.get(self.near[k]) else { continue };
+                let epos = e.pos;
+                let d = ppos.distance(epos);
+                if d>pr && d<NEAR_MISS_DIST && self.rng.gen::<f32>() < 0.02 {
+                    self.score += NEAR_MISS_BONUS;
+                    self.score_by.add(Source::NearMiss, NEAR_MISS_BONUS);
+                    self.earn(Source::NearMiss, 1);
+                    self.events.push(Event::NearMiss(epos));
+                    if let Some(a) = &mut self.adaptive { a.near_miss(); }
+                }
+            }
+        }
+
+        let mut hit_player = false;
+        let pace = self.adapt(Adaptive::speed_scale);
+        self.enemy_grid.query(ppos, pr + registry().max_radius, &mut self.near);
+        for k in 0..self.near.len() {
+            let h = self.near[k];
+            let Some(e) = self.enemies.get(h) else { continue };
+            if ppos.distance(e.pos) > pr + e.r { continue; }
+            let (epos, kind) = (e.pos, e.kind);
+            self.enemies.remove(h);
+            if self.player.invuln>0.0 {
+                self.events.push(Event::EnemyKilled(epos));
+                if let Some(split) = &registry().get(kind).split {
+                    // fling the pieces out to both sides of the dash, further
+                    // than they can close on it before it ends, so it cannot
+                    // eat them too
+                    let piece = registry().get(split.kind);
+                    let fwd = if self.player.vel.length_squared() > 0.0 { self.player.vel.normalize() } else { vec2(1.0, 0.0) };
+                    let line = ppos + fwd * (epos - ppos).dot(fwd);
+                    let clear = pr + piece.radius + piece.speed * pace * self.player.invuln + 6.0;
+                    for i in 0..split.count {
+                        let out = if i % 2 == 0 { fwd.perp() } else { -fwd.perp() };
+                        let pos = line + out * (clear + (i / 2) as f32 * piece.radius * 2.5);
+                        self.enemies.insert(Enemy::new(pos, split.kind, libm::atan2f(out.y, out.x), pace));
+                    }
+                }
+                self.bump_score(Source::Kills, 10);
+                self.earn(Source::Kills, 2);
+                if self.rng.gen::<f32>() < 0.5 { let t = rand_angle(&mut self.rng); self.shards.insert(Shard::new(epos, t)); }
+            } else {
+                hit_player = true;
+            }
+        }
+        if hit_player { self.game_over(DeathCause::Enemy); }
+
+        self.shard_grid.clear(self.arena);
+        for (h, s) in self.shards.iter() { self.shard_grid.insert(h, s.pos); }
+        for s in self.shards.values_mut() { s.prev = s.pos; }
+        let pull = self.upgrades.magnet_speed() + if self.power_magnet>0.0 { 220.0 } else { 0.0 };
+        let radius: f32 = if self.power_magnet>0.0 { 260.0 } else { 180.0 };
+        self.shard_grid.query(ppos, radius.max(pr + SHARD_RADIUS), &mut self.near);
+        for k in 0..self.near.len() {
+            let h = self.near[k];
+            let Some(sh) = self.shards.get_mut(h) else { continue };
+            if ppos.distance(sh.pos) <= pr + sh.r {
+                let spos = sh.pos;
+                self.shards.remove(h);
+                let bonus = (5.0 * (1.0+self.combo)) as i32;
+                self.bump_score(Source::Shards, bonus);
+                self.combo_bonus += bonus - 5;
+                self.earn(Source::Shards, 3 + self.upgrades.shard_currency_bonus());
+                self.events.push(Event::ShardCollected{ pos: spos, bonus });
+            } else {
+                let d = ppos - sh.pos; let dist2 = d.length_squared();
+                if dist2 < radius*radius && dist2>0.0 { sh.pos += d.normalize()* (pull*dt); }
+            }
+        }
+
+        self.powerup_grid.clear(self.arena);
+        for (h, pu) in self.powerups.iter() { self.powerup_grid.insert(h, pu.pos); }
+        self.powerup_grid.query(ppos, pr + POWERUP_RADIUS, &mut self.near);
+        for k in 0..self.near.len() {
+            let h = self.near[k];
+            let Some(pu) = self.powerups.get(h) else { continue };
+            if ppos.distance(pu.pos) > pr + pu.r { continue; }
+            let (pos, kind) = (pu.pos, pu.kind);
+            self.powerups.remove(h);
+            match kind {
+                PowerUpKind::Invuln => self.power_invuln = 5.0,
+                PowerUpKind::Magnet => self.power_magnet = 6.0,
+                PowerUpKind::DoubleDash => self.power_ddash = 8.0,
+            }
+            self.events.push(Event::PowerUp{ pos, kind });
+        }
+
+        if self.combo_t>0.0 { self.combo_t -= dt; if self.combo_t<=0.0 { self.combo = (self.combo-0.5).max(0.0); self.combo_t = 0.0; } }
+
+        let arena = self.arena;
+        self.bullets.retain(|b| {
+            b.prev = b.pos;
+            b.pos += b.vel * dt; b.life -= dt;
+            b.life > 0.0 && b.pos.x >= -10.0 && b.pos.x <= arena.x+10.0 && b.pos.y >= -10.0 && b.pos.y <= arena.y+10.0
+        });
+        self.bullet_grid.clear(self.arena);
+        for (h, b) in self.bullets.iter() { if b.hostile { self.bullet_grid.insert(h, b.pos); } }
+        self.bullet_grid.query(ppos, pr + MAX_BULLET_RADIUS, &mut self.near);
+        for k in 0..self.near.len() {
+            let h = self.near[k];
+            let Some(b) = self.bullets.get(h) else { continue };
+            if ppos.distance(b.pos) > pr + b.r { continue; }
+            let bpos = b.pos;
+            if self.player.invuln>0.0 || self.power_invuln>0.0 { self.events.push(Event::BulletBlocked(bpos)); }
+            else { self.bullets.remove(h); self.game_over(DeathCause::Bullet); }
+        }
+    }
+
+    /// Counts down the calm between waves, which holds while a boss is up,
+    /// then releases the queued wave one spawn every `spacing` seconds.
+    fn direct_waves(&mut self, dt: f32){
+        let scale = self.adapt(Adaptive::spawn_scale);
+        let d = &mut self.director;
+        if d.queue.is_empty() {
+            if self.boss.is_some() { return; }
+            d.calm -= dt;
+            if d.calm <= 0.0 { self.start_wave(); }
+            return;
+        }
+        d.next -= dt;
+        while self.director.next <= 0.0 {
+            let Some((kind, pos)) = self.director.queue.pop_front() else { break };
+            self.spawn(kind, pos);
+            self.director.next += self.director.spacing / scale;
+            if self.director.queue.is_empty() {
+                let [lo, hi] = waves().breather;
+                self.director.calm = (if hi > lo { self.rng.gen_range(lo..hi) } else { lo }) / scale;
+            }
+        }
+    }
+
+    /// Picks a theme for the next wave, spends its budget on the archetypes
+    /// it allows and lays them out in its formation.
+    fn start_wave(&mut self){
+        let (script, reg) = (waves(), registry());
+        let number = self.director.wave + 1;
+        let roll = self.rng.gen::<f32>();
+        let themes = script.themes.iter().enumerate().map(|(i, t)| (i, if t.first_wave <= number { t.weight } else { 0.0 }));
+        let Some(theme) = pick_weighted(themes, roll).map(|i| &script.themes[i]) else { return };
+
+        let mut alive = vec![0; reg.archetypes.len()];
+        for e in self.enemies.values() { alive[e.kind] += 1; }
+        let mut budget = script.budget(number) * self.adapt(Adaptive::spawn_scale);
+        let mut kinds = vec![];
+        loop {
+            let fits = |k: usize| {
+                let a = reg.get(k);
+                a.first_at <= self.time && a.cost <= budget && a.max_alive.is_none_or(|m| alive[k] < m)
+            };
+            let roll = self.rng.gen::<f32>();
+            let Some(k) = pick_weighted(theme.allowed.iter().map(|&k| (k, if fits(k) { reg.get(k).weight } else { 0.0 })), roll) else { break };
+            budget -= reg.get(k).cost;
+            alive[k] += 1;
+            kinds.push(k);
+        }
+        if kinds.is_empty() {
+            // nothing the theme allows fits yet; try again shortly
+            self.director.calm = 1.0;
+            return;
+        }
+
+        let n = kinds.len();
+        let side = self.rng.gen_range(0..4);
+        let base = rand_angle(&mut self.rng);
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let pos = match theme.formation {
+                Formation::Scatter => { let s = self.rng.gen_range(0..4); self.edge_spot(s) }
+                Formation::Edge => self.edge_spot(side),
+                // 0/1 and 2/3 are opposite edges
+                Formation::Pincer => self.edge_spot(side ^ (i & 1)),
+                Formation::Ring { radius } => {
+                    let a = base + std::f32::consts::TAU * i as f32 / n as f32;
+                    let p = self.player.pos + heading(a) * radius;
+                    vec2(clamp(p.x, -24.0, self.arena.x + 24.0), clamp(p.y, -24.0, self.arena.y + 24.0))
+                }
+            };
+            self.director.queue.push_back((kind, pos));
+        }
+        self.director.wave = number;
+        self.director.spacing = theme.spacing;
+        self.director.next = 0.0;
+        self.events.push(Event::WaveStarted { number, name: &theme.announce });
+    }
+
+    /// A multiplier from the adaptive difficulty, or 1 when it is off.
+    fn adapt(&self, f: fn(&Adaptive) -> f32) -> f32 { self.adaptive.as_ref().map_or(1.0, f) }
+
+    /// A random point just outside edge `side`: top, bottom, left, right.
+    fn edge_spot(&mut self, side: usize) -> Vec2 {
+        let rng = &mut self.rng;
+        let m = 24.0;
+        match side {
+            0 => vec2(rng.gen_range(0.0..self.arena.x), -m),
+            1 => vec2(rng.gen_range(0.0..self.arena.x), self.arena.y+m),
+            2 => vec2(-m, rng.gen_range(0.0..self.arena.y)),
+            _ => vec2(self.arena.x+m, rng.gen_range(0.0..self.arena.y)),
+        }
+    }
+
+    /// Announces one spawn of `kind` at `pos`, or wherever its `spawn_at`
+    /// says; it appears once the warning runs out.
+    fn spawn(&mut self, kind: usize, pos: Vec2){
+        let pos = match registry().get(kind).spawn_at { SpawnAt::Edge => pos, SpawnAt::Inside => self.inside_spot() };
+        self.telegraphs.push(Telegraph { pos, kind, timer: waves().warning });
+    }
+
+    /// Brings in the spawns whose warning ran out, each with the rest of its
+    /// group scattered around it.
+    fn materialise(&mut self, dt: f32){
+        for t in &mut self.telegraphs { t.timer -= dt; }
+        let pace = self.adapt(Adaptive::speed_scale);
+        while let Some(i) = self.telegraphs.iter().position(|t| t.timer <= 0.0) {
+            let Telegraph { pos, kind, .. } = self.telegraphs.remove(i);
+            let angle = rand_angle(&mut self.rng);
+            for i in 0..registry().get(kind).group {
+                let jitter = if i == 0 { Vec2::ZERO } else { vec2(self.rng.gen_range(-30.0..30.0), self.rng.gen_range(-30.0..30.0)) };
+                self.enemies.insert(Enemy::new(pos + jitter, kind, angle + i as f32, pace));
+            }
+        }
+    }
+
+    /// A random point in the arena at least `SAFE_SPAWN` from the player, or
+    /// the farthest of a few tries when it is crowded.
+    fn inside_spot(&mut self) -> Vec2 {
+        let mut best = (Vec2::ZERO, -1.0);
+        for _ in 0..8 {
+            let p = vec2(self.rng.gen_range(60.0..self.arena.x-60.0), self.rng.gen_range(60.0..self.arena.y-60.0));
+            let d = p.distance(self.player.pos);
+            if d > best.1 { best = (p, d); }
+            if d >= SAFE_SPAWN { break; }
+        }
+        best.0
+    }
+
+    /// Sets the velocity of every flocking enemy from the player and its
+    /// neighbours of the same kind, as they were at the end of the last tick.
+    fn steer_flocks(&mut self, dt: f32){
+        self.enemy_grid.clear(self.arena);
+        for (h, e) in self.enemies.iter() { self.enemy_grid.insert(h, e.pos); }
+        let mut steer = std::mem::take(&mut self.steer);
+        steer.clear();
+        for (h, e) in self.enemies.iter() {
+            let Movement::Flock { range, turn } = registry().get(e.kind).movement else { continue };
+            self.enemy_grid.query(e.pos, range, &mut self.near);
+            let (mut apart, mut heading, mut centre, mut n) = (Vec2::ZERO, Vec2::ZERO, Vec2::ZERO, 0.0);
+            for &o in &self.near {
+                let Some(f) = self.enemies.get(o) else { continue };
+                let d = e.pos - f.pos;
+                let dist = d.length();
+                if o == h || f.kind != e.kind || dist >= range { continue; }
+                if dist > 0.0 { apart += d / (dist*dist) * range; }
+                heading += f.vel; centre += f.pos; n += 1.0;
+            }
+            let mut want = (self.player.pos - e.pos).normalize_or_zero();
+            if n > 0.0 { want += apart*1.5 + (heading/n).normalize_or_zero()*0.8 + (centre/n - e.pos).normalize_or_zero()*0.6; }
+            steer.push((h, e.vel.lerp(want.normalize_or_zero() * e.speed, (turn*dt).min(1.0))));
+        }
+        for &(h, v) in &steer { if let Some(e) = self.enemies.get_mut(h) { e.vel = v; } }
+        self.steer = steer;
+    }
+
+    fn spawn_shard(&mut self){
+        let rng = &mut self.rng;
+        let pos = vec2(
+            rng.gen_range(40.0..(self.arena.x - 40.0)),
+            rng.gen_range(40.0..(self.arena.y - 40.0)),
+        );
+        let t = rand_angle(rng);
+        self.shards.insert(Shard::new(pos, t));
+    }
+
+    fn earn(&mut self, src: Source, n: i32){ self.credits += n; self.unbanked += n; self.credits_by.add(src, n); }
+
+    fn bump_score(&mut self, src: Source, base: i32){
+        self.score += base;
+        self.score_by.add(src, base);
+        self.combo += COMBO_INC; self.combo_t = COMBO_TIME;
+        self.combo_peak = self.combo_peak.max(self.combo);
+    }
+
+    fn game_over(&mut self, cause: DeathCause){
+        if self.over { return; }
+        self.over = true;
+        self.death = Some((cause, self.player.pos));
+        self.events.push(Event::GameOver(self.player.pos));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{InputSource, Scripted};
+    use crate::replay::{Playback, Replay};
+
+    const LIMIT: u32 = 120 * 120;
+
+    /// Sweeps around the arena in the eight keyboard directions, the only
+    /// ones a replay records, dashing every second or so.
+    fn script() -> Scripted {
+        Scripted::new((0..LIMIT).map(|i| {
+            let a = i as f32 * 0.004;
+            InputState { mv: vec2(libm::cosf(a).round(), libm::sinf(a * 1.7).round()), dash: i % 130 == 0, ..Default::default() }
+        }).collect())
+    }
+
+    /// Steps `w` until it ends or `LIMIT` ticks pass, returning the tick it
+    /// ended on.
+    fn run(w: &mut World, mut next: impl FnMut(&mut World) -> InputState) -> u32 {
+        let mut tick = 0;
+        while !w.over && tick < LIMIT {
+            let input = next(w);
+            w.update(TICK, &input);
+            w.drain_events().for_each(drop);
+            tick += 1;
+        }
+        tick
+    }
+
+    #[test]
+    fn same_seed_and_input_play_out_the_same() {
+        let (mut a, mut b) = (World::new(Upgrades::default(), 42), World::new(Upgrades::default(), 42));
+        let (mut sa, mut sb) = (script(), script());
+        let ta = run(&mut a, |_| sa.poll());
+        let tb = run(&mut b, |_| sb.poll());
+        assert!(a.over, "the script should not survive {} ticks", LIMIT);
+        assert_eq!((ta, a.score, a.credits), (tb, b.score, b.credits));
+        assert_eq!(a.player.pos, b.player.pos);
+    }
+
+    #[test]
+    fn every_split_piece_outlives_the_dash_that_split_them() {
+        let splitter = registry().find("splitter").unwrap();
+        let split = registry().get(splitter).split.clone().unwrap();
+        for dir in [vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, -1.0)] {
+            let mut w = World::new(Upgrades { dash_time: 3, ..Default::default() }, 3);
+            // the dash goes the way the player was already moving
+            w.update(TICK, &InputState { mv: dir, ..Default::default() });
+            let at = w.player.pos + dir.normalize() * 40.0;
+            w.enemies.insert(Enemy::new(at, splitter, 0.0, 1.0));
+            w.update(TICK, &InputState { mv: dir, dash: true, ..Default::default() });
+            while w.player.invuln > 0.0 { w.update(TICK, &InputState { mv: dir, ..Default::default() }); }
+            assert!(!w.over);
+            assert_eq!(w.enemies.values().filter(|e| e.kind == split.kind).count(), split.count as usize);
+            assert!(!w.enemies.values().any(|e| e.kind == splitter));
+        }
+    }
+
+    #[test]
+    fn spawns_are_telegraphed_for_the_warning_time() {
+        let flocker = registry().find("flocker").unwrap();
+        let mut w = World::new(Upgrades::default(), 5);
+        // keep the director out of it
+        w.director.calm = f32::MAX;
+        w.spawn(flocker, vec2(30.0, 30.0));
+        let warned = (waves().warning / TICK).round() as u32;
+        for tick in 0..warned - 1 {
+            w.update(TICK, &InputState::default());
+            assert_eq!((w.telegraphs.len(), w.enemies.values().count()), (1, 0), "tick {}", tick);
+        }
+        for _ in 0..2 { w.update(TICK, &InputState::default()); }
+        assert!(w.telegraphs.is_empty());
+        assert_eq!(w.enemies.values().count() as u32, registry().get(flocker).group);
+    }
+
+    #[test]
+    fn replay_reproduces_score_and_death_tick() {
+        let mut w = World::new(Upgrades { speed: 2, ..Default::default() }, 7);
+        w.adaptive = Some(Adaptive::new(0.1));
+        let mut rec = Replay::new(7, w.upgrades.clone(), Some(0.1));
+        let mut input = script();
+        let died = run(&mut w, |_| { let i = input.poll(); rec.push(&i); i });
+        rec.score = w.score;
+        assert!(w.over);
+
+        // through JSON, as it would be on disk
+        let rec: Replay = serde_json::from_str(&serde_json::to_string(&rec).unwrap()).unwrap();
+        let mut again = rec.world();
+        let mut playback = Playback::new(&rec);
+        let replayed = run(&mut again, |w| playback.next(w));
+        assert_eq!((replayed, again.score), (died, rec.score));
+        assert_eq!(again.death, w.death);
+    }
+}
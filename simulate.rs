@@ -0,0 +1,118 @@
+// `game simulate`: plays batches of headless runs with the scripted bot and
+// prints one row per run plus a summary, for tuning costs and spawn curves.
+// A run that reaches --max-time is reported with cause "timeout" (null in JSON).
+//
+//   game simulate --runs 200 --seed 1 --upgrades 2,1,0,3,1 --max-time 600 --format json --out runs.json
+//
+// `--enemies <file>` plays with another archetype table and `--waves <file>`
+// with another wave script, as in the game. `--adaptive <level>` plays every
+// run with adaptive difficulty starting at that level (0 is neutral).
+
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::adaptive::{Adaptive, Adjustment};
+use crate::bot::Bot;
+use crate::{enemies, waves};
+use crate::sim::{DeathCause, Upgrades, World, TICK};
+
+struct Options { runs: u32, seed: u64, upgrades: Upgrades, max_time: f32, json: bool, out: Option<String>, adaptive: Option<f32> }
+
+#[derive(Serialize)]
+struct RunResult {
+    seed: u64, survival: f32, score: i32, credits: i32, cause: Option<DeathCause>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adaptive: Option<Adjustment>,
+}
+
+#[derive(Serialize, Default)]
+struct Summary { runs: u32, mean_survival: f32, mean_score: f32, mean_credits: f32, deaths: Deaths }
+
+#[derive(Serialize, Default)]
+struct Deaths { enemy: u32, bullet: u32, boss: u32, timeout: u32 }
+
+#[derive(Serialize)]
+struct Report<'a> { upgrades: &'a Upgrades, summary: Summary, runs: Vec<RunResult> }
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let o = parse(args)?;
+    let runs: Vec<RunResult> = (0..o.runs).map(|i| play(o.seed.wrapping_add(i as u64), &o.upgrades, o.max_time, o.adaptive)).collect();
+    let summary = summarize(&runs);
+
+    let text = if o.json {
+        serde_json::to_string_pretty(&Report { upgrades: &o.upgrades, summary, runs }).map_err(|e| e.to_string())?
+    } else {
+        let mut s = String::from("seed,survival,score,credits,cause\n");
+        for r in &runs { s += &format!("{},{:.2},{},{},{}\n", r.seed, r.survival, r.score, r.credits, r.cause.map_or("timeout", |c| c.name())); }
+        eprintln!(
+            "{} runs  survival {:.1}s  score {:.1}  credits {:.1}  deaths enemy {} bullet {} boss {} timeout {}",
+            summary.runs, summary.mean_survival, summary.mean_score, summary.mean_credits,
+            summary.deaths.enemy, summary.deaths.bullet, summary.deaths.boss, summary.deaths.timeout
+        );
+        s
+    };
+    match &o.out {
+        Some(path) => fs::write(path, text).map_err(|e| format!("{}: {}", path, e)),
+        None => { print!("{}", text); Ok(()) }
+    }
+}
+
+fn play(seed: u64, upgrades: &Upgrades, max_time: f32, adaptive: Option<f32>) -> RunResult {
+    let mut w = World::new(upgrades.clone(), seed);
+    w.adaptive = adaptive.map(Adaptive::new);
+    let mut bot = Bot;
+    while !w.over && w.time < max_time {
+        let input = bot.think(&w);
+        w.update(TICK, &input);
+        w.drain_events().for_each(drop);
+    }
+    let cause = w.death.map(|(c, _)| c);
+    RunResult { seed, survival: w.time, score: w.score, credits: w.credits, cause, adaptive: w.adaptive.as_ref().map(Adaptive::adjustment) }
+}
+
+fn summarize(runs: &[RunResult]) -> Summary {
+    let mut s = Summary { runs: runs.len() as u32, ..Default::default() };
+    if runs.is_empty() { return s; }
+    let n = runs.len() as f32;
+    s.mean_survival = runs.iter().map(|r| r.survival).sum::<f32>() / n;
+    s.mean_score = runs.iter().map(|r| r.score as f32).sum::<f32>() / n;
+    s.mean_credits = runs.iter().map(|r| r.credits as f32).sum::<f32>() / n;
+    for r in runs {
+        match r.cause {
+            Some(DeathCause::Enemy) => s.deaths.enemy += 1,
+            Some(DeathCause::Bullet) => s.deaths.bullet += 1,
+            Some(DeathCause::Boss) => s.deaths.boss += 1,
+            None => s.deaths.timeout += 1,
+        }
+    }
+    s
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut o = Options { runs: 100, seed: 1, upgrades: Upgrades::default(), max_time: 600.0, json: false, out: None, adaptive: None };
+    let mut it = args.iter();
+    while let Some(flag) = it.next() {
+        let mut value = || it.next().ok_or_else(|| format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--runs" => o.runs = value()?.parse().map_err(|_| "--runs expects a number")?,
+            "--seed" => o.seed = value()?.parse().map_err(|_| "--seed expects an unsigned integer")?,
+            "--max-time" => o.max_time = value()?.parse().map_err(|_| "--max-time expects seconds")?,
+            "--upgrades" => o.upgrades = parse_upgrades(value()?)?,
+            "--format" => o.json = match value()?.as_str() { "json" => true, "csv" => false, f => return Err(format!("unknown format {}", f)) },
+            "--out" => o.out = Some(value()?.clone()),
+            "--adaptive" => o.adaptive = Some(value()?.parse().map_err(|_| "--adaptive expects a starting level")?),
+            "--enemies" => enemies::install(Path::new(value()?))?,
+            "--waves" => waves::install(Path::new(value()?))?,
+            f => return Err(format!("unknown option {}", f)),
+        }
+    }
+    Ok(o)
+}
+
+/// `speed,dash_cd,dash_time,shard_value,magnet` levels.
+fn parse_upgrades(s: &str) -> Result<Upgrades, String> {
+    let lv: Vec<u32> = s.split(',').map(|v| v.trim().parse().map_err(|_| format!("bad upgrade level {:?}", v))).collect::<Result<_, _>>()?;
+    if lv.len() != 5 { return Err("--upgrades expects 5 levels: speed,dash_cd,dash_time,shard_value,magnet".into()); }
+    Ok(Upgrades { speed: lv[0], dash_cd: lv[1], dash_time: lv[2], shard_value: lv[3], magnet: lv[4] })
+}
@@ -0,0 +1,73 @@
+// Lifetime totals for a profile, fed from the same events the front end
+// already reacts to. Replays and ghosts never reach here, so watching a run
+// does not count as playing it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::sim::{DeathCause, Event, PowerUpKind};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    /// Runs started, including ones abandoned with R or by quitting.
+    pub runs: u32,
+    /// Seconds of simulated play, pauses and menus excluded.
+    pub play_time: f64,
+    pub shards: u32,
+    pub dash_kills: u32,
+    pub near_misses: u32,
+    pub bosses: u32,
+    pub powerups: PowerUps,
+    pub credits_earned: i64,
+    pub credits_spent: i64,
+    pub deaths: Deaths,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerUps { pub invuln: u32, pub magnet: u32, pub double_dash: u32 }
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Deaths { pub enemy: u32, pub bullet: u32, pub boss: u32 }
+
+impl Stats {
+    pub fn record(&mut self, ev: &Event){
+        match ev {
+            Event::EnemyKilled(_) => self.dash_kills += 1,
+            Event::ShardCollected { .. } => self.shards += 1,
+            Event::NearMiss(_) => self.near_misses += 1,
+            Event::BossCleared(_) => self.bosses += 1,
+            Event::PowerUp { kind, .. } => match kind {
+                PowerUpKind::Invuln => self.powerups.invuln += 1,
+                PowerUpKind::Magnet => self.powerups.magnet += 1,
+                PowerUpKind::DoubleDash => self.powerups.double_dash += 1,
+            },
+            _ => {}
+        }
+    }
+
+    pub fn record_death(&mut self, cause: DeathCause){
+        match cause {
+            DeathCause::Enemy => self.deaths.enemy += 1,
+            DeathCause::Bullet => self.deaths.bullet += 1,
+            DeathCause::Boss => self.deaths.boss += 1,
+        }
+    }
+
+    /// Label/value rows for the stats screen.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let t = self.play_time as u64;
+        vec![
+            ("Runs played", self.runs.to_string()),
+            ("Play time", format!("{}h {:02}m {:02}s", t / 3600, t / 60 % 60, t % 60)),
+            ("Shards collected", self.shards.to_string()),
+            ("Enemies destroyed by dash", self.dash_kills.to_string()),
+            ("Near-misses", self.near_misses.to_string()),
+            ("Bosses cleared", self.bosses.to_string()),
+            ("Power-ups: invuln / magnet / double dash", format!("{} / {} / {}", self.powerups.invuln, self.powerups.magnet, self.powerups.double_dash)),
+            ("Credits earned / spent", format!("{} / {}", self.credits_earned, self.credits_spent)),
+            ("Deaths: enemy / bullet / boss", format!("{} / {} / {}", self.deaths.enemy, self.deaths.bullet, self.deaths.boss)),
+        ]
+    }
+}
@@ -0,0 +1,35 @@
+// Maps the fixed logical arena onto whatever window we have. The arena is
+// scaled uniformly to fit and centred; the leftover strips are letterboxed.
+
+use macroquad::prelude::*;
+
+use crate::sim::ARENA;
+
+/// The logical rectangle the window shows: the arena plus letterbox margins.
+pub fn view_rect() -> Rect {
+    let (sw, sh) = (screen_width(), screen_height());
+    let scale = (sw / ARENA.x).min(sh / ARENA.y);
+    let (w, h) = (sw / scale, sh / scale);
+    Rect::new((ARENA.x - w) * 0.5, (ARENA.y - h) * 0.5, w, h)
+}
+
+pub fn camera() -> Camera2D { Camera2D::from_display_rect(view_rect()) }
+
+pub fn screen_to_arena(p: Vec2) -> Vec2 {
+    let v = view_rect();
+    vec2(v.x + p.x / screen_width() * v.w, v.y + p.y / screen_height() * v.h)
+}
+
+/// Blanks everything outside the arena. Drawn last, under the arena camera.
+pub fn draw_letterbox(){
+    let v = view_rect();
+    let c = BLACK;
+    if v.x < 0.0 {
+        draw_rectangle(v.x, v.y, -v.x, v.h, c);
+        draw_rectangle(ARENA.x, v.y, -v.x, v.h, c);
+    }
+    if v.y < 0.0 {
+        draw_rectangle(v.x, v.y, v.w, -v.y, c);
+        draw_rectangle(v.x, ARENA.y, v.w, -v.y, c);
+    }
+}
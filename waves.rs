@@ -0,0 +1,139 @@
+// The wave director's script, read from `data/waves.json` like the enemy
+// table (`--waves <file>` swaps it). Runs alternate between calm stretches and
+// themed waves; each wave gets a budget that grows with the wave number and
+// spends it on enemies the theme allows, placed by the theme's formation.
+
+use macroquad::math::Vec2;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::enemies::registry;
+use crate::save::fnv;
+
+const BUILT_IN: &str = include_str!("data/waves.json");
+
+static WAVES: OnceLock<Waves> = OnceLock::new();
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Formation {
+    /// Each enemy from a random edge.
+    Scatter,
+    /// Everything from one edge.
+    Edge,
+    /// Alternating between two opposite edges.
+    Pincer,
+    /// All at once on a circle around the player.
+    Ring { radius: f32 },
+}
+
+#[derive(Deserialize)]
+pub struct Theme {
+    pub name: String,
+    /// Shown to the player when the wave starts.
+    pub announce: String,
+    pub formation: Formation,
+    /// Archetype names the wave may use; empty allows every archetype.
+    pub kinds: Vec<String>,
+    /// Seconds between spawns within the wave.
+    pub spacing: f32,
+    pub weight: f32,
+    /// First wave number this theme can be picked for.
+    pub first_wave: u32,
+    #[serde(skip)]
+    pub allowed: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct Budget { pub start: f32, pub per_wave: f32, pub max: f32 }
+
+#[derive(Deserialize)]
+pub struct Waves {
+    pub first_wave_at: f32,
+    /// Calm between the last spawn of a wave and the next, rolled in range.
+    pub breather: [f32; 2],
+    /// Seconds each spawn is announced before the enemy appears.
+    pub warning: f32,
+    pub budget: Budget,
+    pub themes: Vec<Theme>,
+    #[serde(skip)]
+    pub fingerprint: u64,
+}
+
+impl Waves {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut w: Waves = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if w.themes.is_empty() { return Err("no themes".into()); }
+        if w.first_wave_at < 0.0 || w.warning < 0.0 || w.breather[0] < 0.0 || w.breather[1] < w.breather[0] {
+            return Err("first_wave_at, warning and breather must not be negative, breather is [min, max]".into());
+        }
+        if w.budget.start <= 0.0 || w.budget.per_wave < 0.0 || w.budget.max < w.budget.start {
+            return Err("budget needs start > 0, per_wave >= 0 and max >= start".into());
+        }
+        let reg = registry();
+        for t in &mut w.themes {
+            if t.spacing < 0.0 || t.weight < 0.0 { return Err(format!("{}: spacing and weight must not be negative", t.name)); }
+            if let Formation::Ring { radius } = t.formation {
+                if radius <= 0.0 { return Err(format!("{}: ring radius must be positive", t.name)); }
+            }
+            t.allowed = if t.kinds.is_empty() { (0..reg.archetypes.len()).collect() } else {
+                t.kinds.iter().map(|k| reg.find(k).ok_or_else(|| format!("{}: unknown archetype {}", t.name, k))).collect::<Result<_, _>>()?
+            };
+        }
+        if !w.themes.iter().any(|t| t.first_wave <= 1 && t.weight > 0.0) { return Err("no theme can open the first wave".into()); }
+        w.fingerprint = fnv(text.as_bytes());
+        Ok(w)
+    }
+
+    /// What wave `n` (counting from 1) has to spend.
+    pub fn budget(&self, n: u32) -> f32 { (self.budget.start + self.budget.per_wave * (n - 1) as f32).min(self.budget.max) }
+}
+
+/// The script in use; the built-in one unless `install` ran first.
+pub fn waves() -> &'static Waves {
+    WAVES.get_or_init(|| Waves::parse(BUILT_IN).unwrap_or_else(|e| panic!("built-in data/waves.json: {}", e)))
+}
+
+/// Replaces the built-in script with the one in `path`. Only works before
+/// the first `World` is made, and after any `enemies::install`.
+pub fn install(path: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let w = Waves::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    WAVES.set(w).map_err(|_| "the wave script is already in use".to_string())
+}
+
+/// Picks an index by weight, `roll` being uniform in 0..1.
+pub fn pick_weighted(weights: impl Iterator<Item = (usize, f32)> + Clone, roll: f32) -> Option<usize> {
+    let total: f32 = weights.clone().map(|(_, w)| w).sum();
+    if total <= 0.0 { return None; }
+    let mut left = roll * total;
+    let mut last = None;
+    for (i, w) in weights.filter(|(_, w)| *w > 0.0) {
+        if left < w { return Some(i); }
+        left -= w;
+        last = Some(i);
+    }
+    // rounding can leave a sliver past the end
+    last
+}
+
+/// Where a run is in the script. Lives in the `World`, which owns the rng.
+pub struct Director {
+    /// Waves started so far.
+    pub wave: u32,
+    /// Seconds until the next wave, counted only while nothing is queued.
+    pub calm: f32,
+    /// Spawns of the current wave not yet released, in order.
+    pub queue: VecDeque<(usize, Vec2)>,
+    pub next: f32,
+    pub spacing: f32,
+}
+
+impl Default for Director {
+    fn default() -> Self {
+        Director { wave: 0, calm: waves().first_wave_at, queue: VecDeque::new(), next: 0.0, spacing: 0.0 }
+    }
+}